@@ -3,6 +3,8 @@ use std::io::{BufRead, BufReader, Read, Write};
 use std::net::TcpStream;
 use std::time::{Duration, SystemTime};
 
+use crate::plan_format::{self, PlanFormat};
+
 #[derive(Debug, Clone)]
 pub struct AgqConfig {
     pub addr: String,
@@ -210,7 +212,7 @@ impl AgqClient {
         let response = read_resp_value(&mut reader)?;
         match response {
             RespValue::BulkString(json_str) => {
-                let plan: crate::plan::WorkflowPlan = serde_json::from_str(&json_str)
+                let plan = plan_format::parse_plan(&json_str, PlanFormat::Json)
                     .map_err(|e| format!("failed to parse plan: {e}"))?;
                 Ok(plan)
             }
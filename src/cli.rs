@@ -1,3 +1,7 @@
+use crate::plan_diagram::DiagramFormat;
+use crate::plan_export::ExportFormat;
+use crate::schema::SchemaKind;
+
 // Version from Cargo.toml - automatically synchronized with releases
 const DISPLAY_VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -11,15 +15,41 @@ Usage:\n\
     agx [OPTIONS] JOBS list [--json]\n\
     agx [OPTIONS] WORKERS list [--json]\n\
     agx [OPTIONS] QUEUE stats [--json]\n\
+    agx [OPTIONS] SCHEMA plan|job\n\
 \n\
 PLAN subcommands:\n\
     PLAN new                 Reset the persisted plan buffer.\n\
-    PLAN add \"<instruction>\"  Append planner-generated steps. Reads STDIN when piped.\n\
-    PLAN validate            Run Delta model validation on current plan.\n\
+    PLAN add \"<instruction>\" [--explain]\n\
+                             Append planner-generated steps. Reads STDIN when piped.\n\
+    PLAN validate [--explain]\n\
+                             Run Delta model validation on current plan.\n\
+    PLAN import --pipeline \"<cmd> | <cmd> ...\"\n\
+                             Replace the plan buffer with the commands of a shell pipeline.\n\
+    PLAN import <file>       Replace the plan buffer with a JSON, YAML or TOML plan file.\n\
     PLAN preview             Pretty-print the current JSON plan buffer.\n\
-    PLAN submit [--json]     Validate the plan and submit to AGQ.\n\
+    PLAN export [--format sh|json|yaml|toml] [--params <json>]\n\
+                             Print the plan as a POSIX shell script that runs without AGX,\n\
+                             or as a plan file.\n\
+    PLAN graph [<plan-id>] --format dot|mermaid\n\
+                             Draw the data flow of the buffer (or an AGQ plan) as a diagram.\n\
+    PLAN run [--input <path>] [--params <json>] [--report <path|->] [--jobs <n>]\n\
+             [--cache | --resume]\n\
+                             Execute the plan locally; optionally write a JSON run report\n\
+                             (`-` writes it to STDERR).\n\
+    PLAN test [<file>] [--json] [--params <json>]\n\
+                             Run the examples of the plan (or a plan file) and report\n\
+                             pass/fail.\n\
+    PLAN check [--json] [--params <json>]\n\
+                             Resolve tools and print each task's argv without running it.\n\
+    PLAN lint [--json]       Report likely mistakes in the plan, ranked error/warn/info.\n\
+    PLAN submit [--json] [--force]\n\
+                             Validate the plan and submit to AGQ. --force submits despite\n\
+                             lint errors.\n\
     PLAN list [--json]       List all stored plans from AGQ.\n\
     PLAN get <plan-id>       View details of a specific plan.\n\
+    PLAN diff <a> <b> [--json]\n\
+                             Compare two plans, each a plan file or an AGQ plan ID.\n\
+    PLAN migrate <file>      Upgrade a plan file to the current schema_version in place.\n\
 \n\
 ACTION subcommands:\n\
     ACTION submit            Execute a plan with data inputs.\n\
@@ -33,6 +63,10 @@ Ops commands:\n\
     WORKERS list             List workers and capabilities (add --json for machine output).\n\
     QUEUE stats              Show queue statistics (add --json for machine output).\n\
 \n\
+Schema commands:\n\
+    SCHEMA plan              Print the JSON Schema of plan files (WorkflowPlan).\n\
+    SCHEMA job               Print the JSON Schema of job envelopes sent to AGQ (JobEnvelope).\n\
+\n\
 Options:\n\
     -h, --help        Print this help text.\n\
     -v, --version     Show the version and this help output.\n\
@@ -43,6 +77,7 @@ Environment variables:\n\
     AGX_BACKEND         Planner backend (ollama or candle).\n\
     AGX_MODEL_ROLE      Model role (echo or delta, default: echo).\n\
     AGX_AUTO_VALIDATE   Auto-run Delta validation before submit (true/false, default: false).\n\
+    AGX_RANDOM_PLAN_ID  Submit plans under random IDs instead of content hashes (true/false, default: false).\n\
     AGX_OLLAMA_MODEL    Ollama model to run when using the Ollama backend (default: phi3:mini).\n\
     AGX_ECHO_MODEL      Path to Echo model (GGUF) for Candle backend.\n\
     AGX_DELTA_MODEL     Path to Delta model (GGUF) for Candle backend.\n\
@@ -57,17 +92,86 @@ pub enum Command {
     Plan(PlanCommand),
     Action(ActionCommand),
     Ops(OpsCommand),
+    Schema(SchemaKind),
 }
 
 #[derive(Debug, Clone)]
 pub enum PlanCommand {
     New,
-    Add { instruction: String },
-    Validate,
+    /// `explain` lists the rewrite rules applied to the planner output
+    Add {
+        instruction: String,
+        explain: bool,
+    },
+    Validate {
+        explain: bool,
+    },
     Preview,
-    Submit { json: bool },
-    List { json: bool },
-    Get { plan_id: String },
+    /// `params` is a JSON object with values for the plan parameters
+    Run {
+        input: Option<String>,
+        params: Option<String>,
+        report: Option<String>,
+        jobs: Option<usize>,
+        resume: bool,
+        cache: bool,
+    },
+    /// `file` is a plan file to test instead of the buffer
+    Test {
+        file: Option<String>,
+        json: bool,
+        params: Option<String>,
+    },
+    Check {
+        json: bool,
+        params: Option<String>,
+    },
+    Lint {
+        json: bool,
+    },
+    /// `force` submits despite lint errors
+    Submit {
+        json: bool,
+        force: bool,
+    },
+    List {
+        json: bool,
+    },
+    Get {
+        plan_id: String,
+    },
+    /// Replace the buffer with the commands of a shell pipeline, or with
+    /// a plan file
+    Import {
+        source: ImportSource,
+    },
+    /// Print the buffer in a form that runs without AGX
+    Export {
+        format: ExportFormat,
+        params: Option<String>,
+    },
+    /// Draw the buffer, or the AGQ plan `plan_id`, as a diagram
+    Graph {
+        plan_id: Option<String>,
+        format: DiagramFormat,
+    },
+    /// `before` and `after` are plan files or AGQ plan IDs
+    Diff {
+        before: String,
+        after: String,
+        json: bool,
+    },
+    Migrate {
+        path: String,
+    },
+}
+
+/// What `PLAN import` reads the plan from
+#[derive(Debug, Clone)]
+pub enum ImportSource {
+    Pipeline(String),
+    /// Path of a JSON, YAML or TOML plan file
+    File(String),
 }
 
 #[derive(Debug, Clone)]
@@ -163,6 +267,7 @@ fn parse_command(tokens: &[String]) -> Result<Command, String> {
         "PLAN" => parse_plan_command(&tokens[1..]),
         "ACTION" => parse_action_command(&tokens[1..]),
         "JOBS" | "WORKERS" | "QUEUE" => parse_ops_command(&tokens),
+        "SCHEMA" => parse_schema_command(&tokens[1..]),
         _ => Err(format!(
             "unknown command: {}. Run `agx --help` for usage.",
             tokens[0]
@@ -172,7 +277,10 @@ fn parse_command(tokens: &[String]) -> Result<Command, String> {
 
 fn parse_plan_command(tokens: &[String]) -> Result<Command, String> {
     if tokens.is_empty() {
-        return Err("PLAN requires a subcommand (new, add, validate, preview, submit).".to_string());
+        return Err(
+            "PLAN requires a subcommand (new, add, validate, preview, run, test, check, lint, submit, list, get, import, export, graph, diff, migrate)."
+                .to_string(),
+        );
     }
 
     let sub = tokens[0].to_lowercase();
@@ -189,14 +297,18 @@ fn parse_plan_command(tokens: &[String]) -> Result<Command, String> {
             Ok(Command::Plan(PlanCommand::New))
         }
         "validate" => {
-            if tokens.len() > 1 {
-                return Err(format!(
-                    "unexpected argument after `PLAN validate`: {}",
-                    tokens[1]
-                ));
+            let mut explain = false;
+
+            for token in &tokens[1..] {
+                match token.as_str() {
+                    "--explain" => explain = true,
+                    _ => {
+                        return Err(format!("unexpected argument after `PLAN validate`: {token}"));
+                    }
+                }
             }
 
-            Ok(Command::Plan(PlanCommand::Validate))
+            Ok(Command::Plan(PlanCommand::Validate { explain }))
         }
         "preview" => {
             if tokens.len() > 1 {
@@ -208,8 +320,143 @@ fn parse_plan_command(tokens: &[String]) -> Result<Command, String> {
 
             Ok(Command::Plan(PlanCommand::Preview))
         }
+        "run" => {
+            let mut input = None;
+            let mut params = None;
+            let mut report = None;
+            let mut jobs = None;
+            let mut resume = false;
+            let mut cache = false;
+            let mut i = 1;
+
+            while i < tokens.len() {
+                match tokens[i].as_str() {
+                    "--input" => {
+                        if i + 1 >= tokens.len() {
+                            return Err("--input requires a path".to_string());
+                        }
+                        input = Some(tokens[i + 1].clone());
+                        i += 2;
+                    }
+                    "--params" => {
+                        params = Some(parse_params_flag(tokens, i)?);
+                        i += 2;
+                    }
+                    "--report" => {
+                        if i + 1 >= tokens.len() {
+                            return Err("--report requires a path or `-`".to_string());
+                        }
+                        report = Some(tokens[i + 1].clone());
+                        i += 2;
+                    }
+                    "--jobs" => {
+                        let value = tokens
+                            .get(i + 1)
+                            .ok_or_else(|| "--jobs requires a number".to_string())?;
+                        let parsed = value
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|jobs| *jobs > 0)
+                            .ok_or_else(|| {
+                                format!("--jobs must be a positive number, got `{value}`")
+                            })?;
+                        jobs = Some(parsed);
+                        i += 2;
+                    }
+                    "--resume" => {
+                        resume = true;
+                        i += 1;
+                    }
+                    "--cache" => {
+                        cache = true;
+                        i += 1;
+                    }
+                    _ => {
+                        return Err(format!(
+                            "unexpected argument after `PLAN run`: {}",
+                            tokens[i]
+                        ));
+                    }
+                }
+            }
+
+            Ok(Command::Plan(PlanCommand::Run {
+                input,
+                params,
+                report,
+                jobs,
+                resume,
+                cache,
+            }))
+        }
+        "test" => {
+            let mut file = None;
+            let mut json = false;
+            let mut params = None;
+            let mut i = 1;
+
+            while i < tokens.len() {
+                match tokens[i].as_str() {
+                    "--json" => {
+                        json = true;
+                        i += 1;
+                    }
+                    "--params" => {
+                        params = Some(parse_params_flag(tokens, i)?);
+                        i += 2;
+                    }
+                    token if file.is_none() && !token.starts_with("--") => {
+                        file = Some(token.to_string());
+                        i += 1;
+                    }
+                    token => {
+                        return Err(format!("unexpected argument after `PLAN test`: {token}"));
+                    }
+                }
+            }
+
+            Ok(Command::Plan(PlanCommand::Test { file, json, params }))
+        }
+        "check" => {
+            let mut json = false;
+            let mut params = None;
+            let mut i = 1;
+
+            while i < tokens.len() {
+                match tokens[i].as_str() {
+                    "--json" => {
+                        json = true;
+                        i += 1;
+                    }
+                    "--params" => {
+                        params = Some(parse_params_flag(tokens, i)?);
+                        i += 2;
+                    }
+                    token => {
+                        return Err(format!("unexpected argument after `PLAN check`: {token}"));
+                    }
+                }
+            }
+
+            Ok(Command::Plan(PlanCommand::Check { json, params }))
+        }
+        "lint" => {
+            let mut json = false;
+
+            for token in &tokens[1..] {
+                match token.as_str() {
+                    "--json" => json = true,
+                    _ => {
+                        return Err(format!("unexpected argument after `PLAN lint`: {token}"));
+                    }
+                }
+            }
+
+            Ok(Command::Plan(PlanCommand::Lint { json }))
+        }
         "submit" => {
             let mut json = false;
+            let mut force = false;
             let mut i = 1;
 
             while i < tokens.len() {
@@ -218,6 +465,10 @@ fn parse_plan_command(tokens: &[String]) -> Result<Command, String> {
                         json = true;
                         i += 1;
                     }
+                    "--force" => {
+                        force = true;
+                        i += 1;
+                    }
                     _ => {
                         return Err(format!(
                             "unexpected argument after `PLAN submit`: {}",
@@ -227,15 +478,29 @@ fn parse_plan_command(tokens: &[String]) -> Result<Command, String> {
                 }
             }
 
-            Ok(Command::Plan(PlanCommand::Submit { json }))
+            Ok(Command::Plan(PlanCommand::Submit { json, force }))
         }
         "add" => {
-            if tokens.len() < 2 {
+            let mut words = &tokens[1..];
+            let mut explain = false;
+
+            if let Some(rest) = words.strip_prefix(&["--explain".to_string()]) {
+                words = rest;
+                explain = true;
+            } else if let Some(rest) = words.strip_suffix(&["--explain".to_string()]) {
+                words = rest;
+                explain = true;
+            }
+
+            if words.is_empty() {
                 return Err("PLAN add requires an instruction string.".to_string());
             }
 
-            let instruction = tokens[1..].join(" ");
-            Ok(Command::Plan(PlanCommand::Add { instruction }))
+            let instruction = words.join(" ");
+            Ok(Command::Plan(PlanCommand::Add {
+                instruction,
+                explain,
+            }))
         }
         "list" => {
             let mut json = false;
@@ -273,13 +538,139 @@ fn parse_plan_command(tokens: &[String]) -> Result<Command, String> {
             let plan_id = tokens[1].clone();
             Ok(Command::Plan(PlanCommand::Get { plan_id }))
         }
+        "import" => match &tokens[1..] {
+            [flag, pipeline] if flag == "--pipeline" => Ok(Command::Plan(PlanCommand::Import {
+                source: ImportSource::Pipeline(pipeline.clone()),
+            })),
+            [flag] if flag == "--pipeline" => {
+                Err("--pipeline requires a pipeline, e.g. --pipeline \"grep ERROR | sort\"".to_string())
+            }
+            [flag, _, extra, ..] if flag == "--pipeline" => Err(format!(
+                "unexpected argument after `PLAN import --pipeline <pipeline>`: {extra}"
+            )),
+            [path] if !path.starts_with("--") => Ok(Command::Plan(PlanCommand::Import {
+                source: ImportSource::File(path.clone()),
+            })),
+            _ => Err("PLAN import requires a plan file or --pipeline \"<pipeline>\".".to_string()),
+        },
+        "export" => {
+            let mut format = ExportFormat::Sh;
+            let mut params = None;
+            let mut i = 1;
+
+            while i < tokens.len() {
+                match tokens[i].as_str() {
+                    "--format" => {
+                        let name = tokens.get(i + 1).ok_or_else(|| {
+                            "--format requires a value (sh, json, yaml or toml).".to_string()
+                        })?;
+                        format = ExportFormat::parse(name).ok_or_else(|| {
+                            format!("unknown export format: {name}. Expected sh, json, yaml or toml.")
+                        })?;
+                        i += 2;
+                    }
+                    "--params" => {
+                        params = Some(parse_params_flag(tokens, i)?);
+                        i += 2;
+                    }
+                    other => {
+                        return Err(format!("unexpected argument after `PLAN export`: {other}"));
+                    }
+                }
+            }
+
+            // Plan files keep their placeholders
+            if params.is_some() && format != ExportFormat::Sh {
+                return Err("--params only applies to --format sh".to_string());
+            }
+
+            Ok(Command::Plan(PlanCommand::Export { format, params }))
+        }
+        "graph" => {
+            let mut plan_id = None;
+            let mut format = None;
+            let mut rest = tokens[1..].iter();
+
+            while let Some(token) = rest.next() {
+                match token.as_str() {
+                    "--format" => {
+                        let name = rest.next().ok_or_else(|| {
+                            "--format requires a value (dot or mermaid).".to_string()
+                        })?;
+                        format = Some(DiagramFormat::parse(name).ok_or_else(|| {
+                            format!("unknown graph format: {name}. Expected dot or mermaid.")
+                        })?);
+                    }
+                    other if plan_id.is_none() && !other.starts_with("--") => {
+                        plan_id = Some(other.to_string());
+                    }
+                    other => {
+                        return Err(format!("unexpected argument after `PLAN graph`: {other}"));
+                    }
+                }
+            }
+
+            let format = format.ok_or_else(|| {
+                "PLAN graph requires --format dot or --format mermaid.".to_string()
+            })?;
+            Ok(Command::Plan(PlanCommand::Graph { plan_id, format }))
+        }
+        "diff" => {
+            let mut json = false;
+            let mut plans = Vec::new();
+
+            for token in &tokens[1..] {
+                match token.as_str() {
+                    "--json" => json = true,
+                    _ if plans.len() < 2 => plans.push(token.clone()),
+                    _ => {
+                        return Err(format!(
+                            "unexpected argument after `PLAN diff <a> <b>`: {token}"
+                        ));
+                    }
+                }
+            }
+
+            let [before, after]: [String; 2] = plans
+                .try_into()
+                .map_err(|_| "PLAN diff requires two plans (files or plan IDs).".to_string())?;
+
+            Ok(Command::Plan(PlanCommand::Diff {
+                before,
+                after,
+                json,
+            }))
+        }
+        "migrate" => {
+            if tokens.len() < 2 {
+                return Err("PLAN migrate requires a plan file.".to_string());
+            }
+
+            if tokens.len() > 2 {
+                return Err(format!(
+                    "unexpected argument after `PLAN migrate <file>`: {}",
+                    tokens[2]
+                ));
+            }
+
+            let path = tokens[1].clone();
+            Ok(Command::Plan(PlanCommand::Migrate { path }))
+        }
         _ => Err(format!(
-            "unknown PLAN subcommand: {}. Expected new/add/validate/preview/submit/list/get.",
+            "unknown PLAN subcommand: {}. Expected new/add/validate/preview/run/test/check/lint/submit/list/get/import/export/graph/diff/migrate.",
             tokens[0]
         )),
     }
 }
 
+/// The JSON object after the `--params` at `index`
+fn parse_params_flag(tokens: &[String], index: usize) -> Result<String, String> {
+    tokens
+        .get(index + 1)
+        .cloned()
+        .ok_or_else(|| "--params requires a JSON object".to_string())
+}
+
 fn parse_action_command(tokens: &[String]) -> Result<Command, String> {
     if tokens.is_empty() {
         return Err("ACTION requires a subcommand (submit).".to_string());
@@ -396,6 +787,16 @@ fn parse_ops_command(tokens: &[String]) -> Result<Command, String> {
     }
 }
 
+fn parse_schema_command(tokens: &[String]) -> Result<Command, String> {
+    match tokens {
+        [name] => SchemaKind::parse(name)
+            .map(Command::Schema)
+            .ok_or_else(|| format!("unknown schema: {name}. Expected plan or job.")),
+        [] => Err("SCHEMA requires a document type (plan or job).".to_string()),
+        [_, extra, ..] => Err(format!("unexpected argument after `SCHEMA`: {extra}")),
+    }
+}
+
 pub fn print_help() {
     println!("{HELP_TEXT}");
 }
@@ -431,8 +832,12 @@ mod tests {
         .expect("valid");
 
         match config.command {
-            Some(Command::Plan(PlanCommand::Add { instruction })) => {
+            Some(Command::Plan(PlanCommand::Add {
+                instruction,
+                explain,
+            })) => {
                 assert_eq!(instruction, "sort and uniq");
+                assert!(!explain);
             }
             other => panic!("unexpected command: {other:?}"),
         }
@@ -444,13 +849,50 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn parse_explain_flags() {
+        for args in [
+            ["--explain", "sort", "and", "uniq"],
+            ["sort", "and", "uniq", "--explain"],
+        ] {
+            let mut tokens = vec!["PLAN".to_string(), "add".to_string()];
+            tokens.extend(args.iter().map(|arg| arg.to_string()));
+
+            match CliConfig::from_args(tokens).expect("valid").command {
+                Some(Command::Plan(PlanCommand::Add {
+                    instruction,
+                    explain: true,
+                })) => assert_eq!(instruction, "sort and uniq"),
+                other => panic!("unexpected command: {other:?}"),
+            }
+        }
+
+        assert!(CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "add".to_string(),
+            "--explain".to_string(),
+        ])
+        .is_err());
+
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "validate".to_string(),
+            "--explain".to_string(),
+        ])
+        .expect("valid");
+        assert!(matches!(
+            config.command,
+            Some(Command::Plan(PlanCommand::Validate { explain: true }))
+        ));
+    }
+
     #[test]
     fn parse_plan_validate_command() {
         let config = CliConfig::from_args(vec!["PLAN".to_string(), "validate".to_string()])
             .expect("valid");
 
         match config.command {
-            Some(Command::Plan(PlanCommand::Validate)) => {}
+            Some(Command::Plan(PlanCommand::Validate { explain: false })) => {}
             other => panic!("unexpected command: {other:?}"),
         }
     }
@@ -468,13 +910,443 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_plan_run_without_input() {
+        let config =
+            CliConfig::from_args(vec!["PLAN".to_string(), "run".to_string()]).expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Run {
+                input: None,
+                params: None,
+                report: None,
+                jobs: None,
+                resume: false,
+                cache: false,
+            })) => {}
+            other => panic!("unexpected command: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn parse_plan_run_with_input() {
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "run".to_string(),
+            "--input".to_string(),
+            "sample.log".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Run { input, report, .. })) => {
+                assert_eq!(input, Some("sample.log".to_string()));
+                assert_eq!(report, None);
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn plan_run_input_requires_path() {
+        let result = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "run".to_string(),
+            "--input".to_string(),
+        ]);
+        match result {
+            Err(msg) => assert!(msg.contains("--input requires a path")),
+            Ok(_) => panic!("Expected error but got Ok"),
+        }
+    }
+
+    #[test]
+    fn parse_plan_run_with_jobs() {
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "run".to_string(),
+            "--jobs".to_string(),
+            "4".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Run { jobs, .. })) => assert_eq!(jobs, Some(4)),
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        let result = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "run".to_string(),
+            "--jobs".to_string(),
+            "0".to_string(),
+        ]);
+        match result {
+            Err(msg) => assert!(msg.contains("--jobs must be a positive number")),
+            Ok(_) => panic!("Expected error but got Ok"),
+        }
+    }
+
+    #[test]
+    fn parse_plan_run_with_resume() {
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "run".to_string(),
+            "--resume".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Run { resume, cache, .. })) => {
+                assert!(resume);
+                assert!(!cache);
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "run".to_string(),
+            "--cache".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Run { resume, cache, .. })) => {
+                assert!(!resume);
+                assert!(cache);
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        assert!(CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "run".to_string(),
+            "--no-cache".to_string(),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn parse_plan_test() {
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "test".to_string(),
+            "--json".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Test { file, json, params })) => {
+                assert_eq!(file, None);
+                assert!(json);
+                assert_eq!(params, None);
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "test".to_string(),
+            "plans/errors.yaml".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Test { file, .. })) => {
+                assert_eq!(file.as_deref(), Some("plans/errors.yaml"));
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        assert!(CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "test".to_string(),
+            "a.json".to_string(),
+            "b.json".to_string(),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn plan_subcommand_errors_agree() {
+        let missing = CliConfig::from_args(vec!["PLAN".to_string()]).unwrap_err();
+        let unknown =
+            CliConfig::from_args(vec!["PLAN".to_string(), "frobnicate".to_string()]).unwrap_err();
+
+        let expected = unknown.split("Expected ").nth(1).unwrap();
+        for subcommand in expected.trim_end_matches('.').split('/') {
+            assert!(missing.contains(subcommand), "{missing} lacks {subcommand}");
+            assert!(
+                HELP_TEXT.contains(&format!("PLAN {subcommand}")),
+                "{subcommand}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_plan_check() {
+        let config =
+            CliConfig::from_args(vec!["PLAN".to_string(), "check".to_string()]).expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Check { json, params })) => {
+                assert!(!json);
+                assert_eq!(params, None);
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "check".to_string(),
+            "--params".to_string(),
+            r#"{"pattern": "error"}"#.to_string(),
+            "--json".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Check { json, params })) => {
+                assert!(json);
+                assert_eq!(params.as_deref(), Some(r#"{"pattern": "error"}"#));
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        assert!(CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "check".to_string(),
+            "--params".to_string(),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn parse_schema_command() {
+        let config =
+            CliConfig::from_args(vec!["SCHEMA".to_string(), "job".to_string()]).expect("valid");
+
+        match config.command {
+            Some(Command::Schema(kind)) => assert_eq!(kind, SchemaKind::Job),
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        assert!(CliConfig::from_args(vec!["SCHEMA".to_string()]).is_err());
+        assert!(CliConfig::from_args(vec!["SCHEMA".to_string(), "task".to_string()]).is_err());
+    }
+
+    #[test]
+    fn parse_plan_import() {
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "import".to_string(),
+            "--pipeline".to_string(),
+            "grep ERROR | sort".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Import {
+                source: ImportSource::Pipeline(pipeline),
+            })) => assert_eq!(pipeline, "grep ERROR | sort"),
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "import".to_string(),
+            "plans/errors.yaml".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Import {
+                source: ImportSource::File(path),
+            })) => assert_eq!(path, "plans/errors.yaml"),
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        for args in [
+            vec![],
+            vec!["--pipeline"],
+            vec!["--json"],
+            vec!["a.json", "b.json"],
+            vec!["--pipeline", "sort", "uniq"],
+        ] {
+            let mut tokens = vec!["PLAN".to_string(), "import".to_string()];
+            tokens.extend(args.into_iter().map(str::to_string));
+            assert!(CliConfig::from_args(tokens).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_plan_export() {
+        for args in [vec![], vec!["--format", "sh"], vec!["--format", "SH"]] {
+            let mut tokens = vec!["PLAN".to_string(), "export".to_string()];
+            tokens.extend(args.into_iter().map(str::to_string));
+
+            let config = CliConfig::from_args(tokens).expect("valid");
+            assert!(matches!(
+                config.command,
+                Some(Command::Plan(PlanCommand::Export {
+                    format: ExportFormat::Sh,
+                    params: None
+                }))
+            ));
+        }
+
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "export".to_string(),
+            "--params".to_string(),
+            "{}".to_string(),
+        ])
+        .expect("valid");
+        match config.command {
+            Some(Command::Plan(PlanCommand::Export { params, .. })) => {
+                assert_eq!(params.as_deref(), Some("{}"));
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        for args in [
+            vec!["--format"],
+            vec!["--format", "bat"],
+            vec!["sh"],
+            vec!["--format", "json", "--params", "{}"],
+        ] {
+            let mut tokens = vec!["PLAN".to_string(), "export".to_string()];
+            tokens.extend(args.into_iter().map(str::to_string));
+            assert!(CliConfig::from_args(tokens).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_plan_graph() {
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "graph".to_string(),
+            "--format".to_string(),
+            "mermaid".to_string(),
+        ])
+        .expect("valid");
+        assert!(matches!(
+            config.command,
+            Some(Command::Plan(PlanCommand::Graph {
+                plan_id: None,
+                format: DiagramFormat::Mermaid
+            }))
+        ));
+
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "graph".to_string(),
+            "plan_abc".to_string(),
+            "--format".to_string(),
+            "dot".to_string(),
+        ])
+        .expect("valid");
+        match config.command {
+            Some(Command::Plan(PlanCommand::Graph { plan_id, format })) => {
+                assert_eq!(plan_id.as_deref(), Some("plan_abc"));
+                assert_eq!(format, DiagramFormat::Dot);
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        for args in [
+            vec![],
+            vec!["plan_abc"],
+            vec!["--format", "svg"],
+            vec!["--format"],
+            vec!["a", "b", "--format", "dot"],
+        ] {
+            let mut tokens = vec!["PLAN".to_string(), "graph".to_string()];
+            tokens.extend(args.into_iter().map(str::to_string));
+            assert!(CliConfig::from_args(tokens).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_plan_diff() {
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "diff".to_string(),
+            "before.json".to_string(),
+            "--json".to_string(),
+            "plan_0123".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Diff {
+                before,
+                after,
+                json,
+            })) => {
+                assert_eq!(before, "before.json");
+                assert_eq!(after, "plan_0123");
+                assert!(json);
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        for args in [vec!["a.json"], vec!["a.json", "b.json", "c.json"]] {
+            let mut tokens = vec!["PLAN".to_string(), "diff".to_string()];
+            tokens.extend(args.into_iter().map(str::to_string));
+            assert!(CliConfig::from_args(tokens).is_err());
+        }
+    }
+
+    #[test]
+    fn parse_plan_migrate() {
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "migrate".to_string(),
+            "plans/dedupe.json".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Migrate { path })) => {
+                assert_eq!(path, "plans/dedupe.json")
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+
+        assert!(CliConfig::from_args(vec!["PLAN".to_string(), "migrate".to_string()]).is_err());
+    }
+
+    #[test]
+    fn parse_plan_run_with_report() {
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "run".to_string(),
+            "--report".to_string(),
+            "-".to_string(),
+        ])
+        .expect("valid");
+
+        match config.command {
+            Some(Command::Plan(PlanCommand::Run { input, report, .. })) => {
+                assert_eq!(input, None);
+                assert_eq!(report, Some("-".to_string()));
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+    }
+
     #[test]
     fn parse_plan_submit_without_json() {
         let config =
             CliConfig::from_args(vec!["PLAN".to_string(), "submit".to_string()]).expect("valid");
 
         match config.command {
-            Some(Command::Plan(PlanCommand::Submit { json: false })) => {}
+            Some(Command::Plan(PlanCommand::Submit {
+                json: false,
+                force: false,
+            })) => {}
             other => panic!("unexpected command: {other:?}"),
         }
     }
@@ -489,11 +1361,42 @@ mod tests {
         .expect("valid");
 
         match config.command {
-            Some(Command::Plan(PlanCommand::Submit { json: true })) => {}
+            Some(Command::Plan(PlanCommand::Submit {
+                json: true,
+                force: false,
+            })) => {}
             other => panic!("unexpected command: {other:?}"),
         }
     }
 
+    #[test]
+    fn parse_plan_lint_and_forced_submit() {
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "lint".to_string(),
+            "--json".to_string(),
+        ])
+        .expect("valid");
+        assert!(matches!(
+            config.command,
+            Some(Command::Plan(PlanCommand::Lint { json: true }))
+        ));
+
+        let config = CliConfig::from_args(vec![
+            "PLAN".to_string(),
+            "submit".to_string(),
+            "--force".to_string(),
+        ])
+        .expect("valid");
+        assert!(matches!(
+            config.command,
+            Some(Command::Plan(PlanCommand::Submit {
+                json: false,
+                force: true
+            }))
+        ));
+    }
+
     #[test]
     fn plan_submit_rejects_unknown_flag() {
         let result = CliConfig::from_args(vec![
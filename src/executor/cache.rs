@@ -0,0 +1,270 @@
+//! On-disk memoization of task outputs for `PLAN run --cache` and
+//! `--resume`.
+//!
+//! Every task is keyed by a hash of its command, its arguments and the key
+//! of what it reads: the plan input is keyed by a digest of its bytes, and a
+//! task reading another task's output uses that task's key. A key therefore
+//! changes whenever the task, anything upstream of it, or the input changes,
+//! and all keys are known before anything runs.
+//!
+//! The cache is bounded: once its entries add up to more than its limit,
+//! the least recently used ones are removed first. Reusing an entry counts
+//! as a use.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+use tempfile::NamedTempFile;
+
+/// Size limit of a cache unless `AGX_CACHE_MAX_BYTES` says otherwise
+pub const DEFAULT_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+
+pub struct TaskCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl TaskCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    /// `$AGX_CACHE_DIR`, or `~/.agx/cache`, limited to
+    /// `$AGX_CACHE_MAX_BYTES` or [`DEFAULT_MAX_BYTES`]
+    pub fn default_location() -> Result<Self, String> {
+        let cache = match std::env::var_os("AGX_CACHE_DIR") {
+            Some(dir) => Self::new(dir),
+            None => {
+                let home = dirs::home_dir()
+                    .ok_or_else(|| "could not determine home directory".to_string())?;
+                Self::new(home.join(".agx").join("cache"))
+            }
+        };
+
+        match std::env::var("AGX_CACHE_MAX_BYTES") {
+            Ok(value) => {
+                let max_bytes = value.parse().map_err(|_| {
+                    format!("AGX_CACHE_MAX_BYTES must be a number of bytes, got {value:?}")
+                })?;
+                Ok(cache.with_max_bytes(max_bytes))
+            }
+            Err(_) => Ok(cache),
+        }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn entry(&self, key: &str) -> PathBuf {
+        self.dir.join(key)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.entry(key).is_file()
+    }
+
+    /// Mark an entry as used, so that pruning keeps it over older ones.
+    /// Best effort: an entry that cannot be touched is just evicted sooner.
+    pub fn touch(&self, key: &str) {
+        if let Ok(file) = File::options().write(true).open(self.entry(key)) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+    }
+
+    /// Remove the least recently used entries until the rest fit within the
+    /// size limit. Returns how many were removed.
+    pub fn prune(&self) -> io::Result<usize> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(error) => return Err(error),
+        };
+
+        let mut files = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            // Entries still being written belong to a running plan
+            if entry.file_name().to_string_lossy().starts_with(".pending-") {
+                continue;
+            }
+
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                files.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+
+        let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+        files.sort();
+
+        let mut removed = 0;
+        for (_, len, path) in files {
+            if total <= self.max_bytes {
+                break;
+            }
+            std::fs::remove_file(&path)?;
+            total -= len;
+            removed += 1;
+        }
+
+        Ok(removed)
+    }
+
+    /// Start a new entry. It is written next to its final location and only
+    /// becomes visible once committed, so a crashed run never leaves a
+    /// partial output behind.
+    pub(crate) fn pending(&self, key: &str) -> Result<PendingEntry, String> {
+        std::fs::create_dir_all(&self.dir).map_err(|error| {
+            format!(
+                "failed to create cache directory {}: {error}",
+                self.dir.display()
+            )
+        })?;
+
+        let file = tempfile::Builder::new()
+            .prefix(".pending-")
+            .tempfile_in(&self.dir)
+            .map_err(|error| format!("failed to create cache entry: {error}"))?;
+
+        Ok(PendingEntry {
+            file,
+            path: self.entry(key),
+        })
+    }
+}
+
+pub(crate) struct PendingEntry {
+    file: NamedTempFile,
+    path: PathBuf,
+}
+
+impl PendingEntry {
+    pub(crate) fn writer(&self) -> io::Result<File> {
+        self.file.as_file().try_clone()
+    }
+
+    pub(crate) fn commit(self) -> io::Result<()> {
+        self.file
+            .persist(&self.path)
+            .map(|_| ())
+            .map_err(|error| error.error)
+    }
+}
+
+/// Key of the plan input: a digest of its bytes
+pub fn input_key<R: Read>(reader: &mut R) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+
+    loop {
+        match reader.read(&mut buffer)? {
+            0 => break,
+            read => hasher.update(&buffer[..read]),
+        }
+    }
+
+    Ok(hex(&hasher.finalize()))
+}
+
+/// Key of a task reading the input identified by `input_key`
+pub fn task_key(command: &str, args: &[String], input_key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(b"agx-task-v1");
+
+    // Length prefixes keep ["a b"] and ["a", "b"] apart
+    for field in std::iter::once(command)
+        .chain(args.iter().map(String::as_str))
+        .chain(std::iter::once(input_key))
+    {
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field.as_bytes());
+    }
+
+    hex(&hasher.finalize())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn task_keys_depend_on_every_field() {
+        let input = input_key(&mut &b"a\nb\n"[..]).unwrap();
+        let key = task_key("sort", &["-r".to_string()], &input);
+
+        assert_eq!(key, task_key("sort", &["-r".to_string()], &input));
+        assert_ne!(key, task_key("sort", &[], &input));
+        assert_ne!(key, task_key("uniq", &["-r".to_string()], &input));
+        assert_ne!(
+            key,
+            task_key(
+                "sort",
+                &["-r".to_string()],
+                &input_key(&mut &b""[..]).unwrap()
+            )
+        );
+        assert_ne!(
+            task_key("grep", &["a b".to_string()], &input),
+            task_key("grep", &["a".to_string(), "b".to_string()], &input)
+        );
+    }
+
+    #[test]
+    fn entries_appear_only_when_committed() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TaskCache::new(dir.path().join("cache"));
+
+        let pending = cache.pending("abc").unwrap();
+        std::io::Write::write_all(&mut pending.writer().unwrap(), b"out").unwrap();
+        assert!(!cache.contains("abc"));
+
+        pending.commit().unwrap();
+        assert!(cache.contains("abc"));
+        assert_eq!(std::fs::read(cache.entry("abc")).unwrap(), b"out");
+    }
+
+    #[test]
+    fn prunes_least_recently_used_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = TaskCache::new(dir.path()).with_max_bytes(8);
+        let now = SystemTime::now();
+
+        for (age, key) in [(30, "old"), (20, "used"), (10, "new")] {
+            std::fs::write(cache.entry(key), b"four").unwrap();
+            File::options()
+                .write(true)
+                .open(cache.entry(key))
+                .unwrap()
+                .set_modified(now - std::time::Duration::from_secs(age))
+                .unwrap();
+        }
+        cache.touch("used");
+
+        assert_eq!(cache.prune().unwrap(), 1);
+        assert!(!cache.contains("old"));
+        assert!(cache.contains("used"));
+        assert!(cache.contains("new"));
+
+        assert_eq!(cache.prune().unwrap(), 0);
+        assert_eq!(
+            TaskCache::new(dir.path().join("missing")).prune().unwrap(),
+            0
+        );
+    }
+}
@@ -0,0 +1,1971 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdout, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread::{self, ScopedJoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::logging;
+use crate::plan::{PlanStep, RunCondition, WorkflowPlan};
+use crate::plan_graph::PlanGraph;
+use crate::registry::{Tool, ToolRegistry};
+
+mod cache;
+mod report;
+
+pub use cache::{input_key, task_key, TaskCache};
+pub use report::{ExecutionReport, TaskReport, TaskStatus, MAX_REPORT_STDERR_BYTES};
+
+use cache::PendingEntry;
+
+/// How often running tasks are checked for completion or timeout
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Where the plan input comes from
+#[derive(Debug, Clone)]
+pub enum ExecutionInput {
+    /// No input; tasks reading the plan input see EOF immediately
+    Empty,
+    /// The process's STDIN
+    Stdin,
+    /// A sample file on disk
+    File(PathBuf),
+}
+
+/// Executes plans locally as streaming pipelines.
+///
+/// Tasks that hand their whole output to exactly one consumer are connected
+/// with OS pipes and run concurrently, like a shell pipeline. Outputs that
+/// feed several tasks, or that are reported alongside other outputs, are
+/// spooled to a per-run temporary directory so memory use stays constant
+/// regardless of input size. Independent branches of the plan run in
+/// parallel, up to `jobs` pipelines at a time.
+///
+/// With a `TaskCache`, every successful task output is stored in the cache,
+/// and a resumed run reuses stored outputs instead of re-running tasks whose
+/// command, arguments and input are unchanged. The plan input is then read
+/// in full before any task starts, and the cache is pruned to its size
+/// limit after the run.
+pub struct Executor {
+    jobs: usize,
+    cache: Option<TaskCache>,
+    resume: bool,
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Self {
+            jobs: thread::available_parallelism().map_or(1, usize::from),
+            cache: None,
+            resume: false,
+        }
+    }
+
+    /// Limit how many independent pipelines run at the same time
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Store task outputs in `cache`
+    pub fn with_cache(mut self, cache: TaskCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Skip tasks whose output is already in the cache
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    pub fn execute(
+        &self,
+        plan: &WorkflowPlan,
+        input: &ExecutionInput,
+        registry: &ToolRegistry,
+    ) -> Result<(), String> {
+        self.execute_to(plan, input, registry, &mut io::stdout())
+    }
+
+    /// Run the plan and write the output of every task without consumers,
+    /// in task order, to `output`.
+    pub fn execute_to(
+        &self,
+        plan: &WorkflowPlan,
+        input: &ExecutionInput,
+        registry: &ToolRegistry,
+        output: &mut (dyn Write + Send),
+    ) -> Result<(), String> {
+        self.run(plan, input, registry, output).into_result()
+    }
+
+    /// Like `execute_to`, but always returns a per-task report. A failed run
+    /// is recorded in `ExecutionReport::error` instead of being returned.
+    pub fn run(
+        &self,
+        plan: &WorkflowPlan,
+        input: &ExecutionInput,
+        registry: &ToolRegistry,
+        output: &mut (dyn Write + Send),
+    ) -> ExecutionReport {
+        let started = Instant::now();
+        let mut tasks: Vec<TaskReport> = plan
+            .tasks
+            .iter()
+            .map(|task| TaskReport::new(task, registry.find_by_id(&task.command)))
+            .collect();
+
+        let result = self.run_plan(plan, input, registry, output, &mut tasks);
+
+        if let Some(cache) = &self.cache {
+            if let Err(error) = cache.prune() {
+                logging::info(&format!("failed to prune the task cache: {error}"));
+            }
+        }
+
+        ExecutionReport {
+            succeeded: result.is_ok(),
+            error: result.err(),
+            duration_ms: millis(started.elapsed()),
+            tasks,
+        }
+    }
+
+    fn run_plan(
+        &self,
+        plan: &WorkflowPlan,
+        input: &ExecutionInput,
+        registry: &ToolRegistry,
+        output: &mut (dyn Write + Send),
+        reports: &mut [TaskReport],
+    ) -> Result<(), String> {
+        if plan.tasks.is_empty() {
+            let mut reader: Box<dyn Read> = match input {
+                ExecutionInput::Empty => Box::new(io::empty()),
+                ExecutionInput::Stdin => Box::new(io::stdin()),
+                ExecutionInput::File(path) => Box::new(open_input_file(path)?),
+            };
+
+            return io::copy(&mut reader, output)
+                .map(|_| ())
+                .map_err(|error| format!("failed to write to STDOUT: {error}"));
+        }
+
+        let graph = PlanGraph::build(plan)?;
+
+        for task in &plan.tasks {
+            if let Some(retry) = &task.retry {
+                retry
+                    .validate()
+                    .map_err(|error| format!("task {}: {error}", task.task_number))?;
+            }
+        }
+
+        // Resolve every tool before starting anything
+        let tools = plan
+            .tasks
+            .iter()
+            .map(|task| {
+                registry
+                    .find_by_id(&task.command)
+                    .ok_or_else(|| format!("unknown tool in plan: {}", task.command))
+            })
+            .collect::<Result<Vec<&Tool>, String>>()?;
+
+        let spool = tempfile::Builder::new()
+            .prefix("agx-run-")
+            .tempdir()
+            .map_err(|error| format!("failed to create spool directory: {error}"))?;
+
+        // Scratch working directory shared by the run's tasks, removed with the spool
+        let workdir = spool.path().join("work");
+        std::fs::create_dir(&workdir)
+            .map_err(|error| format!("failed to create working directory: {error}"))?;
+
+        // STDIN can only be read once; it is spooled when several tasks read
+        // it, when a task reading it may be retried, or when the cache needs a
+        // digest of it before anything runs
+        let readers: Vec<usize> = (0..plan.tasks.len())
+            .filter(|&index| graph.reads_plan_input(index))
+            .collect();
+        let retried = readers
+            .iter()
+            .any(|&index| plan.tasks[index].retry.is_some());
+        let plan_input = prepare_plan_input(
+            input,
+            self.cache.is_some() || readers.len() > 1 || retried,
+            spool.path(),
+        )?;
+
+        let keys = match &self.cache {
+            Some(_) => task_keys(plan, &graph, &tools, &plan_input)?,
+            None => Vec::new(),
+        };
+        // The cache keeps outputs but not exit codes, so tasks whose exit
+        // code decides a run_if always run
+        let mut exit_code_checked = vec![false; plan.tasks.len()];
+        for (index, task) in plan.tasks.iter().enumerate() {
+            if let (Some(RunCondition::ExitCode { .. }), Some(checked)) =
+                (&task.run_if, graph.condition(index))
+            {
+                exit_code_checked[checked] = true;
+            }
+        }
+
+        let cached: Vec<bool> = match &self.cache {
+            Some(cache) if self.resume => keys
+                .iter()
+                .zip(&exit_code_checked)
+                .map(|(key, &checked)| !checked && cache.contains(key))
+                .collect(),
+            _ => vec![false; plan.tasks.len()],
+        };
+
+        let runs = tasks_to_run(&graph, &cached);
+        let output_path = |index: usize| match &self.cache {
+            Some(cache) if !runs[index] => cache.entry(&keys[index]),
+            _ => spool_path(spool.path(), index),
+        };
+
+        for (index, report) in reports.iter_mut().enumerate() {
+            if cached[index] {
+                if let Some(cache) = &self.cache {
+                    cache.touch(&keys[index]);
+                }
+                report.status = TaskStatus::Cached;
+                report.stdout_bytes = std::fs::metadata(output_path(index))
+                    .ok()
+                    .map(|metadata| metadata.len());
+            }
+        }
+
+        let segments = split_segments(plan, &graph, &runs);
+
+        let sinks = graph.sinks();
+        let streamed_sink = match sinks[..] {
+            [sink]
+                if runs[sink] && !graph.is_condition(sink) && plan.tasks[sink].retry.is_none() =>
+            {
+                Some(sink)
+            }
+            _ => None,
+        };
+        let mut output = Some(output);
+
+        // A segment waits for the segments spooling its inputs, unless those
+        // outputs come from the cache, and for the tasks deciding whether it
+        // runs
+        let mut segment_of = vec![0; plan.tasks.len()];
+        for (position, segment) in segments.iter().enumerate() {
+            for &index in segment {
+                segment_of[index] = position;
+            }
+        }
+        let dependencies: Vec<Vec<usize>> = segments
+            .iter()
+            .map(|segment| {
+                graph
+                    .dependencies(segment[0])
+                    .filter(|&dependency| runs[dependency])
+                    .map(|dependency| segment_of[dependency])
+                    .collect()
+            })
+            .collect();
+
+        let cancel = AtomicBool::new(false);
+        let mut states = vec![SegmentState::Pending; segments.len()];
+        let mut errors: Vec<(usize, String)> = Vec::new();
+
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            let mut active = 0;
+
+            loop {
+                // Start every segment whose input is ready, in plan order
+                for position in 0..segments.len() {
+                    if active >= self.jobs || !errors.is_empty() {
+                        break;
+                    }
+
+                    let ready = states[position] == SegmentState::Pending
+                        && dependencies[position]
+                            .iter()
+                            .all(|&dependency| states[dependency].is_finished());
+                    if !ready {
+                        continue;
+                    }
+
+                    let segment = &segments[position];
+                    let last = segment[segment.len() - 1];
+
+                    // The rest of a segment only reads its first task, so
+                    // skipping that task skips the whole segment
+                    match should_run(plan, &graph, segment[0], reports, &output_path) {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            for &index in segment {
+                                reports[index].status = TaskStatus::Skipped;
+                            }
+                            states[position] = SegmentState::Skipped;
+                            continue;
+                        }
+                        Err(error) => {
+                            reports[segment[0]].status = TaskStatus::Failed;
+                            cancel.store(true, Ordering::Relaxed);
+                            errors.push((position, error));
+                            states[position] = SegmentState::Failed;
+                            break;
+                        }
+                    }
+
+                    let source = match graph.sources(segment[0]) {
+                        [] => plan_input.clone(),
+                        &[source] => SegmentSource::File(output_path(source)),
+                        sources => SegmentSource::Concat {
+                            parts: sources.iter().map(|&source| output_path(source)).collect(),
+                            path: spool.path().join(format!("task-{}.in", segment[0] + 1)),
+                        },
+                    };
+
+                    let sink = match output.take() {
+                        Some(output) if Some(last) == streamed_sink => SegmentSink::Stream(output),
+                        other => {
+                            output = other;
+                            SegmentSink::File(output_path(last))
+                        }
+                    };
+
+                    let tasks: Vec<(&PlanStep, &Tool)> = segment
+                        .iter()
+                        .map(|&index| (&plan.tasks[index], tools[index]))
+                        .collect();
+                    let mut segment_reports: Vec<TaskReport> = segment
+                        .iter()
+                        .map(|&index| reports[index].clone())
+                        .collect();
+
+                    let keys = &keys;
+                    let cache_entries = move || -> Vec<Option<PendingEntry>> {
+                        segment
+                            .iter()
+                            .map(|&index| {
+                                let cache = self.cache.as_ref()?;
+
+                                // A cache that cannot be written only costs the entry
+                                cache
+                                    .pending(&keys[index])
+                                    .map_err(|error| logging::info(&error))
+                                    .ok()
+                            })
+                            .collect()
+                    };
+
+                    let sender = sender.clone();
+                    let (workdir, cancel) = (&workdir, &cancel);
+
+                    scope.spawn(move || {
+                        let result = run_segment_retrying(
+                            &tasks,
+                            &mut segment_reports,
+                            &cache_entries,
+                            source,
+                            sink,
+                            workdir,
+                            cancel,
+                        );
+                        // The receiver outlives every worker
+                        let _ = sender.send((position, result, segment_reports));
+                    });
+
+                    states[position] = SegmentState::Running;
+                    active += 1;
+                }
+
+                if active == 0 {
+                    break;
+                }
+
+                let (position, result, segment_reports) =
+                    receiver.recv().expect("segment workers report back");
+                active -= 1;
+
+                for (&index, report) in segments[position].iter().zip(segment_reports) {
+                    reports[index] = report;
+                }
+
+                let first = &plan.tasks[segments[position][0]];
+
+                states[position] = match result {
+                    Ok(()) => SegmentState::Done,
+                    // Tasks that may fail run alone in their segment
+                    Err(error) if !first.on_failure.is_stop() => {
+                        logging::info(&format!(
+                            "continuing after task {} failed: {error}",
+                            first.task_number
+                        ));
+                        SegmentState::Failed
+                    }
+                    Err(error) => {
+                        // Stop the other branches; they end as aborted
+                        cancel.store(true, Ordering::Relaxed);
+                        errors.push((position, error));
+                        SegmentState::Failed
+                    }
+                };
+            }
+        });
+
+        // Report the failure that comes first in the plan, regardless of which
+        // branch happened to fail first
+        if let Some((_, error)) = errors.into_iter().min_by_key(|(position, _)| *position) {
+            return Err(error);
+        }
+
+        // Several outputs are reported one after another in task order;
+        // skipped and tolerated failed tasks have nothing to report
+        if let Some(output) = output {
+            for index in sinks {
+                if !matches!(
+                    reports[index].status,
+                    TaskStatus::Succeeded | TaskStatus::Cached
+                ) {
+                    continue;
+                }
+
+                let mut file = File::open(output_path(index))
+                    .map_err(|error| format!("failed to read spooled output: {error}"))?;
+
+                io::copy(&mut file, output)
+                    .map_err(|error| format!("failed to write final output to STDOUT: {error}"))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SegmentState {
+    Pending,
+    Running,
+    Done,
+    Failed,
+    Skipped,
+}
+
+impl SegmentState {
+    fn is_finished(self) -> bool {
+        matches!(
+            self,
+            SegmentState::Done | SegmentState::Failed | SegmentState::Skipped
+        )
+    }
+}
+
+/// Whether task `index` runs, given how the tasks it depends on ended: every
+/// input must have been produced, a fallback only runs in place of a failed
+/// task, and a `run_if` must hold.
+fn should_run(
+    plan: &WorkflowPlan,
+    graph: &PlanGraph,
+    index: usize,
+    reports: &[TaskReport],
+    output_path: &dyn Fn(usize) -> PathBuf,
+) -> Result<bool, String> {
+    let produced = |index: usize| {
+        matches!(
+            reports[index].status,
+            TaskStatus::Succeeded | TaskStatus::Cached
+        )
+    };
+
+    if !graph.sources(index).iter().all(|&source| produced(source)) {
+        return Ok(false);
+    }
+
+    if let Some(primary) = graph.fallback_for(index) {
+        if !matches!(
+            reports[primary].status,
+            TaskStatus::Failed | TaskStatus::TimedOut | TaskStatus::LimitExceeded
+        ) {
+            return Ok(false);
+        }
+    }
+
+    let (Some(condition), Some(checked)) = (&plan.tasks[index].run_if, graph.condition(index))
+    else {
+        return Ok(true);
+    };
+
+    match condition {
+        // A failed task still has an exit code worth looking at
+        RunCondition::ExitCode { equals, .. } => Ok(reports[checked].exit_code == Some(*equals)),
+        _ if !produced(checked) => Ok(false),
+        RunCondition::OutputEmpty { .. } => Ok(output_len(&output_path(checked))? == 0),
+        RunCondition::OutputNotEmpty { .. } => Ok(output_len(&output_path(checked))? > 0),
+        RunCondition::OutputMatches { pattern, .. } => {
+            let regex = regex::bytes::Regex::new(pattern)
+                .map_err(|error| format!("invalid output_matches pattern: {error}"))?;
+            let output = std::fs::read(output_path(checked))
+                .map_err(|error| format!("failed to read spooled output: {error}"))?;
+
+            Ok(regex.is_match(&output))
+        }
+    }
+}
+
+/// Sleep for `delay`, waking up early when the run is cancelled. Returns
+/// whether the whole delay elapsed.
+fn sleep_unless_cancelled(delay: Duration, cancel: &AtomicBool) -> bool {
+    let deadline = Instant::now() + delay;
+
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+
+        thread::sleep((deadline - now).min(WAIT_POLL_INTERVAL));
+    }
+}
+
+fn output_len(path: &Path) -> Result<u64, String> {
+    std::fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .map_err(|error| format!("failed to read spooled output: {error}"))
+}
+
+/// Decide which tasks have to run. Reported outputs are needed, and so is
+/// the input of every task that runs; a needed output found in the cache
+/// does not require running its task.
+fn tasks_to_run(graph: &PlanGraph, cached: &[bool]) -> Vec<bool> {
+    let mut needed = vec![false; cached.len()];
+    let mut runs = vec![false; cached.len()];
+
+    for sink in graph.sinks() {
+        needed[sink] = true;
+    }
+
+    for &index in graph.order().iter().rev() {
+        if needed[index] && !cached[index] {
+            runs[index] = true;
+
+            for dependency in graph.dependencies(index) {
+                needed[dependency] = true;
+            }
+        }
+    }
+
+    runs
+}
+
+/// Cache key of every task, chained from a digest of the plan input
+fn task_keys(
+    plan: &WorkflowPlan,
+    graph: &PlanGraph,
+    tools: &[&Tool],
+    plan_input: &SegmentSource,
+) -> Result<Vec<String>, String> {
+    let digest = match plan_input {
+        SegmentSource::Null => input_key(&mut io::empty()),
+        SegmentSource::File(path) => input_key(&mut open_input_file(path)?),
+        SegmentSource::Inherit => unreachable!("STDIN is spooled when caching"),
+        SegmentSource::Concat { .. } => unreachable!("the plan input is a single source"),
+    }
+    .map_err(|error| format!("failed to read plan input: {error}"))?;
+
+    let mut keys = vec![String::new(); plan.tasks.len()];
+
+    for &index in graph.order() {
+        // Several inputs are keyed by their keys in the order they are read
+        let mut upstream = match graph.sources(index) {
+            [] => digest.clone(),
+            sources => sources
+                .iter()
+                .map(|&source| keys[source].as_str())
+                .collect::<Vec<_>>()
+                .join("+"),
+        };
+
+        // Whether the task runs at all depends on the tasks gating it
+        for gate in graph
+            .condition(index)
+            .into_iter()
+            .chain(graph.fallback_for(index))
+        {
+            upstream.push('|');
+            upstream.push_str(&keys[gate]);
+        }
+
+        keys[index] = task_key(tools[index].command, &plan.tasks[index].args, &upstream);
+    }
+
+    Ok(keys)
+}
+
+/// Split the tasks that run into chains that can be connected directly with
+/// pipes. A chain ends at a task whose output has no running consumer or
+/// more than one.
+fn split_segments(plan: &WorkflowPlan, graph: &PlanGraph, runs: &[bool]) -> Vec<Vec<usize>> {
+    let mut segments: Vec<Vec<usize>> = Vec::new();
+    let mut segment_of = vec![0; runs.len()];
+
+    // A task that may be skipped is decided before it starts, a task that may
+    // fail without stopping the run or be retried is kept apart from the
+    // tasks around it, and an output a condition looks at must be spooled
+    let isolated =
+        |index: usize| !plan.tasks[index].on_failure.is_stop() || plan.tasks[index].retry.is_some();
+    let decided_at_start = |index: usize| {
+        graph.condition(index).is_some() || graph.fallback_for(index).is_some() || isolated(index)
+    };
+    let spooled = |index: usize| graph.is_condition(index) || isolated(index);
+
+    for index in graph.order().iter().copied().filter(|&index| runs[index]) {
+        let running_consumers = |source: usize| {
+            graph
+                .consumers(source)
+                .iter()
+                .filter(|&&consumer| runs[consumer])
+                .count()
+        };
+
+        match *graph.sources(index) {
+            [source]
+                if runs[source]
+                    && running_consumers(source) == 1
+                    && !decided_at_start(index)
+                    && !spooled(source) =>
+            {
+                let segment = segment_of[source];
+                segments[segment].push(index);
+                segment_of[index] = segment;
+            }
+            _ => {
+                segment_of[index] = segments.len();
+                segments.push(vec![index]);
+            }
+        }
+    }
+
+    segments
+}
+
+fn spool_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("task-{}.out", index + 1))
+}
+
+fn open_input_file(path: &Path) -> Result<File, String> {
+    File::open(path)
+        .map_err(|error| format!("failed to open input file {}: {error}", path.display()))
+}
+
+#[derive(Debug, Clone)]
+enum SegmentSource {
+    Null,
+    Inherit,
+    File(PathBuf),
+    /// Several spooled outputs, concatenated into `path` when the segment
+    /// starts
+    Concat {
+        parts: Vec<PathBuf>,
+        path: PathBuf,
+    },
+}
+
+impl SegmentSource {
+    fn stdio(&self) -> Result<Stdio, String> {
+        match self {
+            SegmentSource::Null => Ok(Stdio::null()),
+            SegmentSource::Inherit => Ok(Stdio::inherit()),
+            SegmentSource::File(path) => open_input_file(path).map(Stdio::from),
+            SegmentSource::Concat { parts, path } => {
+                let mut file = File::create(path)
+                    .map_err(|error| format!("failed to spool concatenated input: {error}"))?;
+
+                for part in parts {
+                    io::copy(&mut open_input_file(part)?, &mut file)
+                        .map_err(|error| format!("failed to spool concatenated input: {error}"))?;
+                }
+
+                open_input_file(path).map(Stdio::from)
+            }
+        }
+    }
+}
+
+enum SegmentSink<'o> {
+    Stream(&'o mut (dyn Write + Send)),
+    File(PathBuf),
+}
+
+/// Open the plan input for the tasks reading it. STDIN is passed through
+/// directly unless `spool_stdin` asks for a copy on disk.
+fn prepare_plan_input(
+    input: &ExecutionInput,
+    spool_stdin: bool,
+    spool: &Path,
+) -> Result<SegmentSource, String> {
+    match input {
+        ExecutionInput::Empty => Ok(SegmentSource::Null),
+        ExecutionInput::File(path) => {
+            open_input_file(path)?;
+            Ok(SegmentSource::File(path.clone()))
+        }
+        ExecutionInput::Stdin if !spool_stdin => Ok(SegmentSource::Inherit),
+        ExecutionInput::Stdin => {
+            let path = spool.join("input");
+            let mut file =
+                File::create(&path).map_err(|error| format!("failed to spool STDIN: {error}"))?;
+            io::copy(&mut io::stdin().lock(), &mut file)
+                .map_err(|error| format!("failed to spool STDIN: {error}"))?;
+
+            Ok(SegmentSource::File(path))
+        }
+    }
+}
+
+struct RunningTask<'a, 'scope> {
+    task: &'a PlanStep,
+    tool: &'a Tool,
+    child: Child,
+    started: Instant,
+    deadline: Instant,
+    finished: Option<Instant>,
+    status: Option<ExitStatus>,
+    /// Killed because another task of the run failed
+    aborted: bool,
+    /// Copies stdout to the next task or the segment sink, counting bytes
+    stdout: Option<ScopedJoinHandle<'scope, io::Result<Pumped>>>,
+    stdout_bytes: Option<u64>,
+    stderr: Option<ScopedJoinHandle<'scope, (Vec<u8>, u64)>>,
+    /// Cache entry receiving a copy of stdout, committed if the task succeeds
+    cache_entry: Option<PendingEntry>,
+    cache_complete: bool,
+}
+
+impl RunningTask<'_, '_> {
+    fn finish(&mut self, status: Option<ExitStatus>) {
+        self.status = status;
+        self.finished = Some(Instant::now());
+    }
+
+    fn is_running(&self) -> bool {
+        self.finished.is_none()
+    }
+}
+
+enum TaskFailure {
+    Status(usize),
+    TimedOut(usize),
+    LimitExceeded(usize, String),
+}
+
+impl TaskFailure {
+    fn position(&self) -> usize {
+        match self {
+            TaskFailure::Status(position)
+            | TaskFailure::TimedOut(position)
+            | TaskFailure::LimitExceeded(position, _) => *position,
+        }
+    }
+}
+
+/// Run a segment, and run it again while its task fails in a way its retry
+/// policy allows. Tasks that retry run alone in their segment, from and to
+/// files that can be read and written again.
+fn run_segment_retrying(
+    tasks: &[(&PlanStep, &Tool)],
+    reports: &mut [TaskReport],
+    cache_entries: &dyn Fn() -> Vec<Option<PendingEntry>>,
+    source: SegmentSource,
+    sink: SegmentSink<'_>,
+    workdir: &Path,
+    cancel: &AtomicBool,
+) -> Result<(), String> {
+    let retry = match tasks {
+        [(task, _)] => task.retry.as_ref(),
+        _ => None,
+    };
+    let (Some(retry), SegmentSink::File(output)) = (retry, &sink) else {
+        return run_segment(
+            tasks,
+            reports,
+            cache_entries(),
+            source,
+            sink,
+            workdir,
+            cancel,
+        );
+    };
+    let output = output.clone();
+
+    let mut attempt = 1;
+
+    loop {
+        let result = run_segment(
+            tasks,
+            reports,
+            cache_entries(),
+            source.clone(),
+            SegmentSink::File(output.clone()),
+            workdir,
+            cancel,
+        );
+        let Err(error) = result else {
+            return result;
+        };
+
+        let report = &reports[0];
+        let retryable = report.status == TaskStatus::Failed
+            && report.exit_code.is_some_and(|code| retry.retries(code));
+        if !retryable || attempt >= retry.max_attempts {
+            return Err(error);
+        }
+
+        let delay = retry.backoff(attempt);
+        logging::info(&format!(
+            "task {} failed on attempt {attempt} of {}, retrying in {} ms: {error}",
+            report.task_number,
+            retry.max_attempts,
+            delay.as_millis()
+        ));
+
+        if !sleep_unless_cancelled(delay, cancel) {
+            return Err(error);
+        }
+        attempt += 1;
+    }
+}
+
+/// Spawn every task of a segment connected by pipes, then wait for all of
+/// them. The first task that fails or times out stops the whole segment.
+/// `reports` holds one entry per task and is filled in as tasks finish.
+/// Outputs of tasks that succeed are committed to their `cache_entries`.
+fn run_segment(
+    tasks: &[(&PlanStep, &Tool)],
+    reports: &mut [TaskReport],
+    mut cache_entries: Vec<Option<PendingEntry>>,
+    source: SegmentSource,
+    sink: SegmentSink<'_>,
+    workdir: &Path,
+    cancel: &AtomicBool,
+) -> Result<(), String> {
+    let writer: Box<dyn Write + Send + '_> = match sink {
+        SegmentSink::Stream(output) => Box::new(output),
+        SegmentSink::File(path) => Box::new(
+            File::create(path).map_err(|error| format!("failed to create spool file: {error}"))?,
+        ),
+    };
+
+    thread::scope(|scope| {
+        let mut running: Vec<RunningTask> = Vec::with_capacity(tasks.len());
+        let mut upstream: Option<ChildStdout> = None;
+        let mut writer = Some(writer);
+        let mut sink_pump = None;
+
+        for (position, &(task, tool)) in tasks.iter().enumerate() {
+            // Every pipe goes through a counting copy so the report can tell
+            // how much each task wrote and output limits can be enforced.
+            let stdin = match upstream {
+                Some(_) => Ok(Stdio::piped()),
+                None => source.stdio(),
+            };
+
+            let mut child = match stdin.and_then(|stdin| spawn(task, tool, stdin, workdir)) {
+                Ok(child) => child,
+                Err(error) => {
+                    reports[position].status = TaskStatus::Failed;
+                    abort(&mut running);
+                    finish_reports(&mut running, reports, None);
+                    return Err(error);
+                }
+            };
+
+            if let (Some(mut from), Some(mut to)) = (upstream.take(), child.stdin.take()) {
+                let producer = running.last_mut().expect("upstream task is running");
+                let limit = producer.tool.sandbox.output_bytes;
+                let tee = cache_writer(&producer.cache_entry);
+                producer.stdout = Some(scope.spawn(move || pump(&mut from, &mut to, limit, tee)));
+            }
+
+            let cache_entry = cache_entries.get_mut(position).and_then(Option::take);
+
+            let stderr = child.stderr.take().map(|mut stderr| {
+                scope.spawn(move || read_bounded(&mut stderr, MAX_REPORT_STDERR_BYTES))
+            });
+
+            if position + 1 < tasks.len() {
+                upstream = child.stdout.take();
+            } else if let (Some(mut from), Some(mut to)) = (child.stdout.take(), writer.take()) {
+                let limit = tool.sandbox.output_bytes;
+                let tee = cache_writer(&cache_entry);
+                sink_pump = Some(scope.spawn(move || pump(&mut from, &mut to, limit, tee)));
+            }
+
+            let started = Instant::now();
+
+            running.push(RunningTask {
+                task,
+                tool,
+                child,
+                started,
+                deadline: started + Duration::from_secs(u64::from(task.timeout_secs)),
+                finished: None,
+                status: None,
+                aborted: false,
+                stdout: None,
+                stdout_bytes: None,
+                stderr,
+                cache_entry,
+                cache_complete: false,
+            });
+        }
+
+        let mut failure = wait_all(&mut running, cancel);
+        let mut write_error = None;
+
+        for task in running.iter_mut() {
+            if let Some(Ok(Ok(pumped))) = task.stdout.take().map(|handle| handle.join()) {
+                task.stdout_bytes = Some(pumped.bytes);
+                task.cache_complete = pumped.cache_complete;
+            }
+        }
+
+        if let (Some(handle), Some(last)) = (sink_pump, running.last_mut()) {
+            match handle.join() {
+                Ok(Ok(pumped)) => {
+                    last.stdout_bytes = Some(pumped.bytes);
+                    last.cache_complete = pumped.cache_complete;
+                }
+                Ok(Err(error)) => write_error = Some(error.to_string()),
+                Err(_) => write_error = Some("output writer panicked".to_string()),
+            }
+        }
+
+        // A limit hit by an earlier task explains any failure downstream of it
+        if let Some(exceeded) = find_exceeded_limit(&running) {
+            if failure
+                .as_ref()
+                .is_none_or(|failure| exceeded.position() <= failure.position())
+            {
+                failure = Some(exceeded);
+            }
+        }
+
+        finish_reports(&mut running, reports, failure.as_ref());
+
+        for (position, task) in running.iter_mut().enumerate() {
+            let Some(entry) = task.cache_entry.take() else {
+                continue;
+            };
+
+            if reports[position].status == TaskStatus::Succeeded && task.cache_complete {
+                if let Err(error) = entry.commit() {
+                    logging::info(&format!("failed to store cached output: {error}"));
+                }
+            }
+        }
+
+        match failure {
+            Some(TaskFailure::TimedOut(position)) => {
+                let task = &running[position];
+                return Err(format!(
+                    "task {} ({}) timed out after {}s",
+                    task.task.task_number, task.tool.command, task.task.timeout_secs
+                ));
+            }
+            Some(TaskFailure::LimitExceeded(position, limit)) => {
+                let task = &running[position];
+                return Err(format!(
+                    "task {} ({}) exceeded its {limit}",
+                    task.task.task_number, task.tool.command
+                ));
+            }
+            Some(TaskFailure::Status(position)) => {
+                let task = &running[position];
+
+                return Err(format!(
+                    "task {} command '{}' failed with status {}: {}",
+                    task.task.task_number,
+                    task.tool.command,
+                    task.status
+                        .map(|status| status.to_string())
+                        .unwrap_or_else(|| "unknown".to_string()),
+                    reports[position].stderr.trim()
+                ));
+            }
+            None => {}
+        }
+
+        match write_error {
+            Some(error) => Err(format!("failed to write task output: {error}")),
+            None => Ok(()),
+        }
+    })
+}
+
+/// First task, in pipeline order, that ran into a sandbox limit
+fn find_exceeded_limit(running: &[RunningTask]) -> Option<TaskFailure> {
+    use std::os::unix::process::ExitStatusExt;
+
+    running.iter().enumerate().find_map(|(position, task)| {
+        let sandbox = &task.tool.sandbox;
+
+        if let (Some(limit), Some(bytes)) = (sandbox.output_bytes, task.stdout_bytes) {
+            if bytes > limit {
+                return Some(TaskFailure::LimitExceeded(
+                    position,
+                    format!("output limit of {limit} bytes"),
+                ));
+            }
+        }
+
+        match (
+            sandbox.cpu_secs,
+            task.status.and_then(|status| status.signal()),
+        ) {
+            (Some(secs), Some(libc::SIGXCPU)) => Some(TaskFailure::LimitExceeded(
+                position,
+                format!("CPU time limit of {secs}s"),
+            )),
+            _ => None,
+        }
+    })
+}
+
+/// Record how every started task of a segment ended
+fn finish_reports(
+    running: &mut [RunningTask],
+    reports: &mut [TaskReport],
+    failure: Option<&TaskFailure>,
+) {
+    use std::os::unix::process::ExitStatusExt;
+
+    for (position, task) in running.iter_mut().enumerate() {
+        let report = &mut reports[position];
+
+        report.status = match (failure, task.status) {
+            (Some(TaskFailure::TimedOut(failed)), _) if *failed == position => TaskStatus::TimedOut,
+            (Some(TaskFailure::LimitExceeded(failed, _)), _) if *failed == position => {
+                TaskStatus::LimitExceeded
+            }
+            _ if task.aborted => TaskStatus::Aborted,
+            (_, Some(status)) if is_accepted(task.tool, status) => TaskStatus::Succeeded,
+            _ => TaskStatus::Failed,
+        };
+
+        if let Some(status) = task.status {
+            report.exit_code = status.code();
+            report.signal = status.signal();
+            report.exit_code_accepted = matches!(
+                status.code(),
+                Some(code) if code != 0 && task.tool.ok_exit_codes.contains(&code)
+            );
+        }
+
+        if let Some(finished) = task.finished {
+            report.duration_ms = Some(millis(finished - task.started));
+        }
+
+        report.stdout_bytes = task.stdout_bytes;
+        report.attempts += 1;
+
+        if let Some(Ok((stderr, total))) = task.stderr.take().map(|handle| handle.join()) {
+            report.stderr_truncated = total > stderr.len() as u64;
+            report.stderr = String::from_utf8_lossy(&stderr).into_owned();
+            report.stderr_bytes = Some(total);
+        }
+    }
+}
+
+/// Start a task inside its tool's sandbox: an allowlisted environment, the
+/// run's scratch directory as working directory, and resource limits.
+fn spawn(task: &PlanStep, tool: &Tool, stdin: Stdio, workdir: &Path) -> Result<Child, String> {
+    let sandbox = tool.sandbox;
+    let mut command = Command::new(tool.command);
+
+    command
+        .args(&task.args)
+        .env_clear()
+        .envs(
+            sandbox
+                .env_allowlist
+                .iter()
+                .filter_map(|name| std::env::var_os(name).map(|value| (name, value))),
+        )
+        .env("HOME", workdir)
+        .env("TMPDIR", workdir)
+        .current_dir(workdir)
+        // Run each task in its own process group so a timeout also reaches
+        // anything the tool spawned.
+        .process_group(0)
+        .stdin(stdin)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    // (resource, soft limit, extra headroom for the hard limit). Going over
+    // the soft CPU limit raises SIGXCPU; the hard limit would be a SIGKILL
+    // indistinguishable from a timeout.
+    let limits = [
+        (libc::RLIMIT_CPU, sandbox.cpu_secs, 1),
+        (libc::RLIMIT_AS, sandbox.memory_bytes, 0),
+        (libc::RLIMIT_NOFILE, sandbox.open_files, 0),
+    ];
+
+    // Only async-signal-safe calls are made between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            for (resource, limit, headroom) in limits {
+                let Some(limit) = limit else { continue };
+
+                let mut current = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                if libc::getrlimit(resource, &mut current) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                // An unprivileged process can lower but never raise its hard limit
+                let soft = (limit as libc::rlim_t).min(current.rlim_max);
+                let lowered = libc::rlimit {
+                    rlim_cur: soft,
+                    rlim_max: soft.saturating_add(headroom).min(current.rlim_max),
+                };
+                if libc::setrlimit(resource, &lowered) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+
+            Ok(())
+        });
+    }
+
+    command
+        .spawn()
+        .map_err(|error| format!("failed to start command '{}': {error}", tool.command))
+}
+
+/// Wait for every task, enforcing deadlines. Returns the first failure in
+/// pipeline order; once a task fails the remaining ones are killed. When
+/// `cancel` is set by another branch, every task is killed as aborted.
+fn wait_all(running: &mut [RunningTask], cancel: &AtomicBool) -> Option<TaskFailure> {
+    loop {
+        if cancel.load(Ordering::Relaxed) {
+            abort(running);
+            return None;
+        }
+
+        let mut pending = false;
+        let mut failure = None;
+
+        for (position, task) in running.iter_mut().enumerate() {
+            if !task.is_running() {
+                continue;
+            }
+
+            match task.child.try_wait() {
+                Ok(Some(status)) => {
+                    task.finish(Some(status));
+
+                    if !is_accepted(task.tool, status) {
+                        failure = failure.or(Some(TaskFailure::Status(position)));
+                    }
+                }
+                Ok(None) if Instant::now() >= task.deadline => {
+                    kill_process_group(&task.child);
+                    let status = task.child.wait().ok();
+                    task.finish(status);
+                    failure = failure.or(Some(TaskFailure::TimedOut(position)));
+                }
+                Ok(None) => pending = true,
+                Err(_) => {
+                    kill_process_group(&task.child);
+                    let status = task.child.wait().ok();
+                    task.finish(status);
+                    failure = failure.or(Some(TaskFailure::Status(position)));
+                }
+            }
+        }
+
+        if failure.is_some() {
+            abort(running);
+            return failure;
+        }
+
+        if !pending {
+            return None;
+        }
+
+        thread::sleep(WAIT_POLL_INTERVAL);
+    }
+}
+
+fn is_accepted(tool: &Tool, status: ExitStatus) -> bool {
+    use std::os::unix::process::ExitStatusExt;
+
+    match status.code() {
+        Some(value) => tool.ok_exit_codes.contains(&value),
+        // A consumer that stops reading early is not a failure of the producer
+        None => status.success() || status.signal() == Some(libc::SIGPIPE),
+    }
+}
+
+/// Kill and reap every task that is still running
+fn abort(running: &mut [RunningTask]) {
+    for task in running.iter_mut().filter(|task| task.is_running()) {
+        kill_process_group(&task.child);
+        let status = task.child.wait().ok();
+        task.finish(status);
+        task.aborted = true;
+    }
+}
+
+fn kill_process_group(child: &Child) {
+    // The child is not reaped yet, so its pid (and process group id) cannot
+    // have been reused.
+    let pgid = child.id() as libc::pid_t;
+
+    unsafe {
+        libc::kill(-pgid, libc::SIGKILL);
+    }
+}
+
+/// Drain `reader` completely, keeping at most `limit` bytes. Returns the
+/// kept bytes and the total number of bytes read.
+fn read_bounded<R: Read>(reader: &mut R, limit: usize) -> (Vec<u8>, u64) {
+    let mut kept = Vec::new();
+    let mut total = 0u64;
+    let mut buffer = [0u8; 8192];
+
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => {
+                let room = limit.saturating_sub(kept.len());
+                kept.extend_from_slice(&buffer[..read.min(room)]);
+                total += read as u64;
+            }
+        }
+    }
+
+    (kept, total)
+}
+
+struct Pumped {
+    /// Bytes the task wrote
+    bytes: u64,
+    /// The whole output reached the cache entry
+    cache_complete: bool,
+}
+
+fn cache_writer(entry: &Option<PendingEntry>) -> Option<File> {
+    entry.as_ref().and_then(|entry| entry.writer().ok())
+}
+
+/// Copy a task's stdout to `to`, and to `tee` if given, until EOF or until it
+/// has written more than `limit` bytes; anything past the limit is
+/// discarded. A consumer that exits early ends the copy quietly, and
+/// dropping `from` then lets the producer see SIGPIPE.
+fn pump<W: Write + ?Sized>(
+    from: &mut ChildStdout,
+    to: &mut W,
+    limit: Option<u64>,
+    mut tee: Option<File>,
+) -> io::Result<Pumped> {
+    let limit = limit.unwrap_or(u64::MAX);
+    let mut total = 0u64;
+    let mut reached_end = false;
+    let mut buffer = [0u8; 64 * 1024];
+
+    loop {
+        let read = match from.read(&mut buffer) {
+            Ok(0) => {
+                reached_end = true;
+                break;
+            }
+            Ok(read) => read,
+            Err(_) => break,
+        };
+
+        let room = usize::try_from(limit.saturating_sub(total)).unwrap_or(usize::MAX);
+        let chunk = &buffer[..read.min(room)];
+        total += read as u64;
+
+        // Failing to write the cache only costs the cache entry
+        if tee
+            .as_mut()
+            .is_some_and(|file| file.write_all(chunk).is_err())
+        {
+            tee = None;
+        }
+
+        match to.write_all(chunk) {
+            Ok(()) => {}
+            Err(error) if error.kind() == io::ErrorKind::BrokenPipe => break,
+            Err(error) => return Err(error),
+        }
+
+        if total > limit {
+            break;
+        }
+    }
+
+    to.flush()?;
+
+    Ok(Pumped {
+        bytes: total,
+        cache_complete: reached_end && tee.is_some(),
+    })
+}
+
+fn millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{step, FailurePolicy, RetryPolicy};
+    use crate::registry::SandboxProfile;
+
+    fn run(plan: &WorkflowPlan, input: &[u8]) -> Result<Vec<u8>, String> {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), input).unwrap();
+
+        let mut output = Vec::new();
+        Executor::new().execute_to(
+            plan,
+            &ExecutionInput::File(file.path().to_path_buf()),
+            &ToolRegistry::new(),
+            &mut output,
+        )?;
+
+        Ok(output)
+    }
+
+    #[test]
+    fn runs_linear_pipeline() {
+        let plan = WorkflowPlan {
+            tasks: vec![step(1, "sort", &[], None), step(2, "uniq", &[], None)],
+            ..Default::default()
+        };
+
+        assert_eq!(run(&plan, b"b\na\nb\n").unwrap(), b"a\nb\n");
+    }
+
+    #[test]
+    fn fans_out_from_plan_input_and_reports_sinks_in_order() {
+        let plan = WorkflowPlan {
+            tasks: vec![
+                step(1, "grep", &["ERROR"], None),
+                step(2, "sort", &[], Some(1)),
+                step(3, "grep", &["WARN"], None),
+            ],
+            ..Default::default()
+        };
+
+        let output = run(&plan, b"ERROR b\nWARN x\nERROR a\n").unwrap();
+        assert_eq!(output, b"ERROR a\nERROR b\nWARN x\n");
+    }
+
+    #[test]
+    fn fan_in_concatenates_inputs_in_listed_order() {
+        let plan = WorkflowPlan {
+            tasks: vec![
+                PlanStep {
+                    inputs_from_tasks: vec![3, 2],
+                    ..step(1, "cut", &["-c1-5"], None)
+                },
+                step(2, "grep", &["WARN"], None),
+                step(3, "grep", &["ERROR"], None),
+            ],
+            ..Default::default()
+        };
+
+        let output = run(&plan, b"ERROR b\nWARN x\nERROR a\n").unwrap();
+        assert_eq!(output, b"ERROR\nERROR\nWARN \n");
+    }
+
+    #[test]
+    fn spools_output_consumed_by_several_tasks() {
+        let plan = WorkflowPlan {
+            tasks: vec![
+                step(1, "grep", &["a"], None),
+                step(2, "sort", &["-r"], Some(1)),
+                step(3, "sort", &[], Some(1)),
+            ],
+            ..Default::default()
+        };
+
+        let output = run(&plan, b"ab\nb\naa\n").unwrap();
+        assert_eq!(output, b"ab\naa\naa\nab\n");
+    }
+
+    #[test]
+    fn streams_inputs_larger_than_pipe_buffers() {
+        // Several MB through a pipeline would deadlock if a task's input
+        // were written before its output is read.
+        let mut input = Vec::new();
+        for line in 0..200_000 {
+            input.extend_from_slice(format!("line {}\n", line % 1000).as_bytes());
+        }
+
+        let plan = WorkflowPlan {
+            tasks: vec![
+                step(1, "grep", &["line"], None),
+                step(2, "sort", &[], None),
+                step(3, "uniq", &[], None),
+            ],
+            ..Default::default()
+        };
+
+        let output = run(&plan, &input).unwrap();
+        assert_eq!(output.iter().filter(|&&byte| byte == b'\n').count(), 1000);
+    }
+
+    #[test]
+    fn parallel_branches_report_in_task_order() {
+        let plan = WorkflowPlan {
+            tasks: vec![
+                step(1, "grep", &["-c", "ERROR"], None),
+                step(2, "grep", &["-c", "WARN"], None),
+                step(3, "grep", &["ERROR"], None),
+                step(4, "sort", &["-r"], Some(3)),
+                step(5, "grep", &["-c", "INFO"], None),
+            ],
+            ..Default::default()
+        };
+        let input = b"ERROR a\nWARN x\nERROR b\nINFO y\nWARN z\n";
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), input).unwrap();
+
+        for jobs in [1, 4] {
+            let mut output = Vec::new();
+            Executor::new()
+                .with_jobs(jobs)
+                .execute_to(
+                    &plan,
+                    &ExecutionInput::File(file.path().to_path_buf()),
+                    &ToolRegistry::new(),
+                    &mut output,
+                )
+                .unwrap();
+
+            assert_eq!(output, b"2\n2\nERROR b\nERROR a\n1\n", "jobs = {jobs}");
+        }
+    }
+
+    #[test]
+    fn failing_branch_stops_branches_not_yet_started() {
+        let plan = WorkflowPlan {
+            tasks: vec![
+                step(1, "grep", &["-c", "a"], None),
+                step(2, "cut", &["--no-such-flag"], None),
+                step(3, "grep", &["-c", "b"], None),
+                step(4, "sort", &[], Some(3)),
+            ],
+            ..Default::default()
+        };
+
+        // One job at a time runs the branches [1], [2] and [3, 4] in plan order
+        let mut output = Vec::new();
+        let report = Executor::new().with_jobs(1).run(
+            &plan,
+            &ExecutionInput::Empty,
+            &ToolRegistry::new(),
+            &mut output,
+        );
+
+        let error = report.error.as_deref().unwrap_or_default();
+        assert!(error.contains("task 2 command 'cut' failed"), "{error}");
+
+        let statuses: Vec<TaskStatus> = report.tasks.iter().map(|task| task.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                TaskStatus::Succeeded,
+                TaskStatus::Failed,
+                TaskStatus::NotRun,
+                TaskStatus::NotRun
+            ]
+        );
+    }
+
+    #[test]
+    fn resume_reuses_cached_outputs_up_to_the_edited_task() {
+        let cache = tempfile::tempdir().unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), b"ab\nb\naa\nab\n").unwrap();
+
+        let run_resumed = |plan: &WorkflowPlan| {
+            let mut output = Vec::new();
+            let report = Executor::new()
+                .with_cache(TaskCache::new(cache.path()))
+                .with_resume(true)
+                .run(
+                    plan,
+                    &ExecutionInput::File(file.path().to_path_buf()),
+                    &ToolRegistry::new(),
+                    &mut output,
+                );
+            assert!(report.succeeded, "{:?}", report.error);
+
+            let statuses: Vec<TaskStatus> = report.tasks.iter().map(|task| task.status).collect();
+            (output, statuses)
+        };
+
+        let mut plan = WorkflowPlan {
+            tasks: vec![
+                step(1, "grep", &["a"], None),
+                step(2, "sort", &[], None),
+                step(3, "uniq", &[], None),
+            ],
+            ..Default::default()
+        };
+
+        let (first, statuses) = run_resumed(&plan);
+        assert_eq!(first, b"aa\nab\n");
+        assert_eq!(statuses, vec![TaskStatus::Succeeded; 3]);
+
+        let (second, statuses) = run_resumed(&plan);
+        assert_eq!(second, first);
+        assert_eq!(statuses, vec![TaskStatus::Cached; 3]);
+
+        plan.tasks[2].args = vec!["-c".to_string()];
+        let (edited, statuses) = run_resumed(&plan);
+        assert_eq!(edited, b"      1 aa\n      2 ab\n");
+        assert_eq!(
+            statuses,
+            vec![
+                TaskStatus::Cached,
+                TaskStatus::Cached,
+                TaskStatus::Succeeded
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_tool_is_rejected() {
+        let plan = WorkflowPlan {
+            tasks: vec![step(1, "rm", &["-rf"], None)],
+            ..Default::default()
+        };
+
+        assert!(run(&plan, b"")
+            .unwrap_err()
+            .contains("unknown tool in plan"));
+    }
+
+    #[test]
+    fn reports_failing_task_with_stderr() {
+        let plan = WorkflowPlan {
+            tasks: vec![
+                step(1, "sort", &[], None),
+                step(2, "grep", &["--no-such-flag"], None),
+            ],
+            ..Default::default()
+        };
+
+        let error = run(&plan, b"a\n").unwrap_err();
+        assert!(error.contains("task 2 command 'grep' failed"), "{error}");
+    }
+
+    fn sh_tool(sandbox: SandboxProfile) -> Tool {
+        Tool {
+            id: "sh",
+            command: "sh",
+            description: "test tool",
+            patterns: &[],
+            ok_exit_codes: &[0],
+            sandbox,
+        }
+    }
+
+    /// Run a single `sh -c script` task, returning its output, its report
+    /// and the spool directory whose `work` subdirectory it ran in
+    fn run_sh(
+        script: &str,
+        timeout_secs: u32,
+        sandbox: SandboxProfile,
+    ) -> (Result<Vec<u8>, String>, TaskReport, tempfile::TempDir) {
+        let sh = sh_tool(sandbox);
+        let mut task = step(1, "sh", &["-c", script], None);
+        task.timeout_secs = timeout_secs;
+
+        let spool = tempfile::tempdir().unwrap();
+        let output = spool.path().join("out");
+        let workdir = spool.path().join("work");
+        std::fs::create_dir(&workdir).unwrap();
+        let mut reports = vec![TaskReport::new(&task, Some(&sh))];
+
+        let result = run_segment(
+            &[(&task, &sh)],
+            &mut reports,
+            vec![None],
+            SegmentSource::Null,
+            SegmentSink::File(output.clone()),
+            &workdir,
+            &AtomicBool::new(false),
+        )
+        .map(|()| std::fs::read(&output).unwrap());
+
+        (result, reports.remove(0), spool)
+    }
+
+    #[test]
+    fn kills_task_after_timeout() {
+        // The background sleep keeps STDOUT open unless the whole group dies
+        let started = Instant::now();
+        let (result, report, _) = run_sh("sleep 30 & wait", 1, SandboxProfile::DEFAULT);
+        let error = result.unwrap_err();
+
+        assert!(error.contains("task 1 (sh) timed out after 1s"), "{error}");
+        assert!(started.elapsed() < Duration::from_secs(4));
+        assert_eq!(report.status, TaskStatus::TimedOut);
+    }
+
+    #[test]
+    fn sandbox_clears_environment_and_uses_scratch_directory() {
+        let sandbox = SandboxProfile {
+            env_allowlist: &["PATH"],
+            ..SandboxProfile::DEFAULT
+        };
+
+        let (result, _, spool) = run_sh("env | cut -d= -f1 | sort; pwd", 10, sandbox);
+        let output = String::from_utf8(result.unwrap()).unwrap();
+        let mut lines: Vec<&str> = output.lines().collect();
+        let cwd = lines.pop().unwrap();
+
+        // sh itself may export PWD, SHLVL and `_`
+        lines.retain(|name| !matches!(*name, "PWD" | "OLDPWD" | "SHLVL" | "_"));
+        assert_eq!(lines, vec!["HOME", "PATH", "TMPDIR"]);
+        assert_eq!(
+            std::fs::canonicalize(cwd).unwrap(),
+            std::fs::canonicalize(spool.path().join("work")).unwrap()
+        );
+    }
+
+    #[test]
+    fn sandbox_enforces_output_limit() {
+        let sandbox = SandboxProfile {
+            output_bytes: Some(1024),
+            ..SandboxProfile::DEFAULT
+        };
+
+        let (result, report, _) = run_sh("yes", 10, sandbox);
+        let error = result.unwrap_err();
+
+        assert!(error.contains("output limit of 1024 bytes"), "{error}");
+        assert_eq!(report.status, TaskStatus::LimitExceeded);
+    }
+
+    /// Run a single `sh -c script` task under `retry` until it succeeds or
+    /// runs out of attempts
+    fn run_sh_retrying(script: &str, retry: RetryPolicy) -> (Result<Vec<u8>, String>, TaskReport) {
+        let sh = sh_tool(SandboxProfile::DEFAULT);
+        let mut task = step(1, "sh", &["-c", script], None);
+        task.retry = Some(retry);
+
+        let spool = tempfile::tempdir().unwrap();
+        let output = spool.path().join("out");
+        let mut reports = vec![TaskReport::new(&task, Some(&sh))];
+
+        let result = run_segment_retrying(
+            &[(&task, &sh)],
+            &mut reports,
+            &|| vec![None],
+            SegmentSource::Null,
+            SegmentSink::File(output.clone()),
+            spool.path(),
+            &AtomicBool::new(false),
+        )
+        .map(|()| std::fs::read(&output).unwrap());
+
+        (result, reports.remove(0))
+    }
+
+    fn retry_on(codes: &[i32]) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff_base_ms: 10,
+            backoff_max_ms: 20,
+            retryable_exit_codes: codes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn retries_task_until_it_succeeds() {
+        // Fails the first time only; the working directory survives retries
+        let script = "if [ -e seen ]; then echo ok; else echo partial; touch seen; exit 75; fi";
+        let (result, report) = run_sh_retrying(script, retry_on(&[75]));
+
+        assert_eq!(result.unwrap(), b"ok\n");
+        assert_eq!(report.status, TaskStatus::Succeeded);
+        assert_eq!(report.attempts, 2);
+    }
+
+    #[test]
+    fn does_not_retry_other_exit_codes_or_beyond_max_attempts() {
+        let (result, report) = run_sh_retrying("exit 1", retry_on(&[75]));
+        assert!(result.is_err());
+        assert_eq!(report.attempts, 1);
+
+        let (result, report) = run_sh_retrying("exit 75", retry_on(&[]));
+        assert!(result.is_err());
+        assert_eq!(report.attempts, 3);
+        assert_eq!(report.exit_code, Some(75));
+    }
+
+    #[test]
+    fn sandbox_enforces_cpu_limit() {
+        let sandbox = SandboxProfile {
+            cpu_secs: Some(1),
+            ..SandboxProfile::DEFAULT
+        };
+
+        let (result, report, _) = run_sh("while :; do :; done", 30, sandbox);
+        let error = result.unwrap_err();
+
+        assert!(error.contains("CPU time limit of 1s"), "{error}");
+        assert_eq!(report.status, TaskStatus::LimitExceeded);
+    }
+
+    #[test]
+    fn report_counts_bytes_and_accepted_exit_codes() {
+        let plan = WorkflowPlan {
+            tasks: vec![step(1, "sort", &[], None), step(2, "grep", &["zzz"], None)],
+            ..Default::default()
+        };
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), b"b\na\n").unwrap();
+
+        let mut output = Vec::new();
+        let report = Executor::new().run(
+            &plan,
+            &ExecutionInput::File(file.path().to_path_buf()),
+            &ToolRegistry::new(),
+            &mut output,
+        );
+
+        assert!(report.succeeded, "{:?}", report.error);
+        assert_eq!(report.tasks[0].stdout_bytes, Some(4));
+        assert_eq!(report.tasks[0].status, TaskStatus::Succeeded);
+        assert!(report.tasks[0].binary_path.is_some());
+        assert_eq!(report.tasks[1].exit_code, Some(1));
+        assert!(report.tasks[1].exit_code_accepted);
+        assert_eq!(report.tasks[1].stdout_bytes, Some(0));
+    }
+
+    #[test]
+    fn report_records_failure_without_returning_error() {
+        let plan = WorkflowPlan {
+            tasks: vec![
+                step(1, "grep", &["--no-such-flag"], None),
+                step(2, "sort", &[], None),
+            ],
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        let report = Executor::new().run(
+            &plan,
+            &ExecutionInput::Empty,
+            &ToolRegistry::new(),
+            &mut output,
+        );
+
+        assert!(!report.succeeded);
+        assert_eq!(report.tasks[0].status, TaskStatus::Failed);
+        assert_eq!(report.tasks[0].exit_code, Some(2));
+        assert!(report.tasks[0].stderr_bytes.unwrap_or(0) > 0);
+    }
+
+    fn run_with_report(plan: &WorkflowPlan, input: &[u8]) -> (ExecutionReport, Vec<u8>) {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), input).unwrap();
+
+        let mut output = Vec::new();
+        let report = Executor::new().run(
+            plan,
+            &ExecutionInput::File(file.path().to_path_buf()),
+            &ToolRegistry::new(),
+            &mut output,
+        );
+
+        (report, output)
+    }
+
+    fn statuses(report: &ExecutionReport) -> Vec<TaskStatus> {
+        report.tasks.iter().map(|task| task.status).collect()
+    }
+
+    #[test]
+    fn run_if_skips_tasks_and_their_consumers() {
+        let plan = WorkflowPlan {
+            tasks: vec![
+                step(1, "grep", &["ERROR"], None),
+                PlanStep {
+                    run_if: Some(RunCondition::OutputNotEmpty { task: 1 }),
+                    ..step(2, "sort", &[], Some(1))
+                },
+                PlanStep {
+                    run_if: Some(RunCondition::OutputMatches {
+                        task: 1,
+                        pattern: "ERROR [0-9]+".to_string(),
+                    }),
+                    ..step(3, "grep", &["WARN"], None)
+                },
+                step(4, "cut", &["-c1-4"], Some(3)),
+            ],
+            ..Default::default()
+        };
+
+        let (report, output) = run_with_report(&plan, b"ERROR b\nWARN x\n");
+        assert!(report.succeeded, "{:?}", report.error);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                TaskStatus::Succeeded,
+                TaskStatus::Succeeded,
+                TaskStatus::Skipped,
+                TaskStatus::Skipped
+            ]
+        );
+        assert_eq!(output, b"ERROR b\n");
+    }
+
+    #[test]
+    fn run_if_checks_exit_codes_and_empty_output() {
+        let plan = WorkflowPlan {
+            tasks: vec![
+                step(1, "grep", &["-c", "FATAL"], None),
+                PlanStep {
+                    run_if: Some(RunCondition::ExitCode { task: 1, equals: 1 }),
+                    ..step(2, "tr", &["a-z", "A-Z"], None)
+                },
+                PlanStep {
+                    run_if: Some(RunCondition::OutputEmpty { task: 1 }),
+                    ..step(3, "sort", &[], None)
+                },
+                step(4, "uniq", &[], Some(1)),
+            ],
+            ..Default::default()
+        };
+
+        let (report, output) = run_with_report(&plan, b"warn x\n");
+        assert!(report.succeeded, "{:?}", report.error);
+        assert_eq!(report.tasks[2].status, TaskStatus::Skipped);
+        assert_eq!(output, b"WARN X\n0\n");
+    }
+
+    #[test]
+    fn exit_code_condition_sees_failed_tasks() {
+        let plan = WorkflowPlan {
+            tasks: vec![
+                PlanStep {
+                    on_failure: FailurePolicy::Continue,
+                    ..step(1, "grep", &["--no-such-flag"], None)
+                },
+                PlanStep {
+                    run_if: Some(RunCondition::ExitCode { task: 1, equals: 2 }),
+                    ..step(2, "tr", &["a-z", "A-Z"], None)
+                },
+                PlanStep {
+                    run_if: Some(RunCondition::OutputEmpty { task: 1 }),
+                    ..step(3, "sort", &[], None)
+                },
+                step(4, "uniq", &[], Some(1)),
+            ],
+            ..Default::default()
+        };
+
+        let (report, output) = run_with_report(&plan, b"a\n");
+        assert!(report.succeeded, "{:?}", report.error);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                TaskStatus::Failed,
+                TaskStatus::Succeeded,
+                TaskStatus::Skipped,
+                TaskStatus::Skipped
+            ]
+        );
+        assert_eq!(output, b"A\n");
+    }
+
+    #[test]
+    fn continue_policy_skips_only_dependent_tasks() {
+        let plan = WorkflowPlan {
+            tasks: vec![
+                PlanStep {
+                    on_failure: FailurePolicy::Continue,
+                    ..step(1, "cut", &["--no-such-flag"], None)
+                },
+                step(2, "sort", &[], Some(1)),
+                step(3, "grep", &["b"], None),
+            ],
+            ..Default::default()
+        };
+
+        let (report, output) = run_with_report(&plan, b"a\nb\n");
+        assert!(report.succeeded, "{:?}", report.error);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                TaskStatus::Failed,
+                TaskStatus::Skipped,
+                TaskStatus::Succeeded
+            ]
+        );
+        assert_eq!(output, b"b\n");
+    }
+
+    #[test]
+    fn fallback_runs_only_when_its_task_fails() {
+        let plan_with = |args: &[&str]| WorkflowPlan {
+            tasks: vec![
+                PlanStep {
+                    on_failure: FailurePolicy::Fallback(2),
+                    ..step(1, "cut", args, None)
+                },
+                step(2, "sort", &["-r"], None),
+                step(3, "uniq", &[], Some(2)),
+            ],
+            ..Default::default()
+        };
+
+        let (report, output) = run_with_report(&plan_with(&["--no-such-flag"]), b"a\nb\n");
+        assert!(report.succeeded, "{:?}", report.error);
+        assert_eq!(
+            statuses(&report),
+            vec![
+                TaskStatus::Failed,
+                TaskStatus::Succeeded,
+                TaskStatus::Succeeded
+            ]
+        );
+        assert_eq!(output, b"b\na\n");
+
+        let (report, output) = run_with_report(&plan_with(&["-c1"]), b"a\nb\n");
+        assert_eq!(
+            statuses(&report),
+            vec![
+                TaskStatus::Succeeded,
+                TaskStatus::Skipped,
+                TaskStatus::Skipped
+            ]
+        );
+        assert_eq!(output, b"a\nb\n");
+    }
+}
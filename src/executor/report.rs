@@ -0,0 +1,91 @@
+//! Machine-readable record of a local plan run.
+
+use serde::Serialize;
+
+use crate::plan::PlanStep;
+use crate::registry::Tool;
+
+/// Stderr kept per task in the report
+pub const MAX_REPORT_STDERR_BYTES: usize = 4 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    NotRun,
+    /// Output reused from the task cache
+    Cached,
+    Succeeded,
+    Failed,
+    TimedOut,
+    /// Stopped by its tool's sandbox limits
+    LimitExceeded,
+    /// Killed because another task of the run failed
+    Aborted,
+    /// Not started: its `run_if` did not hold, one of its inputs was not
+    /// produced, or it is a fallback for a task that did not fail
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskReport {
+    pub task_number: u32,
+    pub command: String,
+    pub args: Vec<String>,
+    /// Absolute path of the binary the tool resolved to on `$PATH`
+    pub binary_path: Option<String>,
+    pub status: TaskStatus,
+    pub exit_code: Option<i32>,
+    /// Signal that terminated the task, if it did not exit normally
+    pub signal: Option<i32>,
+    /// True when a non-zero exit code was accepted via `Tool::ok_exit_codes`
+    pub exit_code_accepted: bool,
+    pub duration_ms: Option<u64>,
+    pub stdout_bytes: Option<u64>,
+    pub stderr_bytes: Option<u64>,
+    /// First `MAX_REPORT_STDERR_BYTES` of stderr
+    pub stderr: String,
+    pub stderr_truncated: bool,
+    /// Times the task was started, more than one when it was retried
+    pub attempts: u32,
+}
+
+impl TaskReport {
+    pub fn new(task: &PlanStep, tool: Option<&Tool>) -> Self {
+        Self {
+            task_number: task.task_number,
+            command: task.command.clone(),
+            args: task.args.clone(),
+            binary_path: tool
+                .and_then(Tool::resolve_binary)
+                .map(|path| path.display().to_string()),
+            status: TaskStatus::NotRun,
+            exit_code: None,
+            signal: None,
+            exit_code_accepted: false,
+            duration_ms: None,
+            stdout_bytes: None,
+            stderr_bytes: None,
+            stderr: String::new(),
+            stderr_truncated: false,
+            attempts: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionReport {
+    pub succeeded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    pub tasks: Vec<TaskReport>,
+}
+
+impl ExecutionReport {
+    pub fn into_result(self) -> Result<(), String> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
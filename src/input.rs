@@ -20,13 +20,8 @@ impl InputSummary {
             content: Vec::new(),
         }
     }
-}
-
-impl InputCollector {
-    pub fn collect() -> Result<InputSummary, io::Error> {
-        let mut content = Vec::new();
-        io::stdin().read_to_end(&mut content)?;
 
+    pub fn from_bytes(content: Vec<u8>) -> Self {
         let bytes = content.len();
         let lines = if bytes == 0 {
             0
@@ -36,13 +31,22 @@ impl InputCollector {
         let is_empty = bytes == 0;
         let is_probably_binary = content.iter().any(|&byte| byte == b'\0');
 
-        Ok(InputSummary {
+        Self {
             bytes,
             lines,
             is_empty,
             is_probably_binary,
             content,
-        })
+        }
+    }
+}
+
+impl InputCollector {
+    pub fn collect() -> Result<InputSummary, io::Error> {
+        let mut content = Vec::new();
+        io::stdin().read_to_end(&mut content)?;
+
+        Ok(InputSummary::from_bytes(content))
     }
 
     pub fn stdin_is_terminal() -> bool {
@@ -1,17 +1,35 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-use crate::plan::WorkflowPlan;
+use crate::plan::{
+    schema_version, FailurePolicy, PlanParameter, RetryPolicy, RunCondition, WorkflowPlan,
+    SCHEMA_VERSION,
+};
+use crate::plan_graph::topological_order;
+use crate::plan_params;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Most tasks a job submitted to AGQ may have
+pub const MAX_TASKS: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct JobEnvelope {
+    #[serde(default = "schema_version")]
+    pub schema_version: String,
     pub job_id: String,
     pub plan_id: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub plan_description: Option<String>,
     pub tasks: Vec<JobTask>,
+    /// Parameters the `{{input.<name>}}` placeholders in task args refer to
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<PlanParameter>,
+    /// JSON Schema the inputs of an action over this plan must satisfy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_schema: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct JobTask {
     pub task_number: u32,
     pub command: String,
@@ -21,6 +39,15 @@ pub struct JobTask {
     pub timeout_secs: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub input_from_task: Option<u32>,
+    /// Tasks whose outputs are concatenated, in this order, into STDIN
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs_from_tasks: Vec<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_if: Option<RunCondition>,
+    #[serde(default, skip_serializing_if = "FailurePolicy::is_stop")]
+    pub on_failure: FailurePolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
 }
 
 fn default_timeout() -> u32 {
@@ -33,6 +60,16 @@ pub enum EnvelopeValidationError {
     TooManyTasks(usize),
     NonMonotonicTasks,
     BadInputReference(u32),
+    ConflictingInputs(u32),
+    CyclicInputs(u32),
+    BadConditionReference(u32),
+    InvalidCondition(u32, String),
+    BadFallbackReference(u32),
+    SharedFallback(u32),
+    InvalidRetry(u32, String),
+    InvalidParameters(String),
+    InvalidInputSchema(String),
+    UnsupportedSchemaVersion(String),
     FirstTaskNotOne(u32),
 }
 
@@ -49,6 +86,42 @@ impl std::fmt::Display for EnvelopeValidationError {
             EnvelopeValidationError::BadInputReference(task) => {
                 write!(f, "input_from_task references invalid task {task}")
             }
+            EnvelopeValidationError::ConflictingInputs(task) => {
+                write!(
+                    f,
+                    "task {task} sets both input_from_task and inputs_from_tasks"
+                )
+            }
+            EnvelopeValidationError::CyclicInputs(task) => {
+                write!(f, "task {task} is part of a dependency cycle")
+            }
+            EnvelopeValidationError::BadConditionReference(task) => {
+                write!(f, "run_if references invalid task {task}")
+            }
+            EnvelopeValidationError::InvalidCondition(task, error) => {
+                write!(f, "task {task} has an invalid run_if: {error}")
+            }
+            EnvelopeValidationError::BadFallbackReference(task) => {
+                write!(f, "on_failure references invalid fallback task {task}")
+            }
+            EnvelopeValidationError::SharedFallback(task) => {
+                write!(f, "task {task} is the fallback of several tasks")
+            }
+            EnvelopeValidationError::InvalidRetry(task, error) => {
+                write!(f, "task {task} has an invalid retry: {error}")
+            }
+            EnvelopeValidationError::InvalidParameters(error) => {
+                write!(f, "invalid plan parameters: {error}")
+            }
+            EnvelopeValidationError::InvalidInputSchema(error) => {
+                write!(f, "invalid input_schema: {error}")
+            }
+            EnvelopeValidationError::UnsupportedSchemaVersion(version) => {
+                write!(
+                    f,
+                    "unsupported schema_version {version} (expected {SCHEMA_VERSION})"
+                )
+            }
             EnvelopeValidationError::FirstTaskNotOne(n) => {
                 write!(f, "first task number must be 1 (found {n})")
             }
@@ -57,14 +130,16 @@ impl std::fmt::Display for EnvelopeValidationError {
 }
 
 impl JobEnvelope {
+    /// Without a plan_id in the plan or an override, the plan_id is the
+    /// [`content_plan_id`](Self::content_plan_id)
     pub fn from_plan(
         plan: WorkflowPlan,
         job_id: String,
-        plan_id_override: String,
+        plan_id_override: Option<String>,
         plan_description_override: Option<String>,
     ) -> Self {
         // Use plan's IDs if provided, otherwise use overrides
-        let plan_id = plan.plan_id.unwrap_or(plan_id_override);
+        let plan_id = plan.plan_id.or(plan_id_override);
         let plan_description = plan.plan_description.or(plan_description_override);
 
         // Convert tasks and ensure proper numbering (defensive: normalize_for_execution should have done this)
@@ -78,18 +153,63 @@ impl JobEnvelope {
                 args: task.args,
                 timeout_secs: task.timeout_secs,
                 input_from_task: task.input_from_task,
+                inputs_from_tasks: task.inputs_from_tasks,
+                run_if: task.run_if,
+                on_failure: task.on_failure,
+                retry: task.retry,
             })
             .collect();
 
-        Self {
+        let mut envelope = Self {
+            schema_version: plan.schema_version,
             job_id,
-            plan_id,
+            plan_id: String::new(),
             plan_description,
             tasks,
-        }
+            parameters: plan.parameters,
+            input_schema: plan.input_schema,
+        };
+        envelope.plan_id = match plan_id {
+            Some(plan_id) => plan_id,
+            None => envelope.content_plan_id(),
+        };
+
+        envelope
+    }
+
+    /// `plan_<hash>` of everything that decides what the plan does: its
+    /// tasks, parameters and input schema, but not its description or IDs.
+    /// Identical plans therefore share an ID.
+    pub fn content_plan_id(&self) -> String {
+        let content = serde_json::json!({
+            "tasks": self.tasks,
+            "parameters": self.parameters,
+            "input_schema": self.input_schema,
+        });
+
+        let mut canonical = String::new();
+        write_canonical(&content, &mut canonical);
+
+        let mut hasher = Sha256::new();
+        hasher.update(b"agx-plan-v1");
+        hasher.update(canonical.as_bytes());
+
+        let digest = hasher.finalize();
+        let hex: String = digest[..16]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+
+        format!("plan_{hex}")
     }
 
     pub fn validate(&self, max_tasks: usize) -> Result<(), EnvelopeValidationError> {
+        if self.schema_version != SCHEMA_VERSION {
+            return Err(EnvelopeValidationError::UnsupportedSchemaVersion(
+                self.schema_version.clone(),
+            ));
+        }
+
         if self.tasks.is_empty() {
             return Err(EnvelopeValidationError::EmptyTasks);
         }
@@ -110,20 +230,116 @@ impl JobEnvelope {
             }
         }
 
-        let mut seen = std::collections::HashSet::new();
+        // Task numbers are 1..=len at this point, so a reference maps
+        // directly to a position
+        let position = |ref_id: u32| {
+            (ref_id != 0 && ref_id as usize <= self.tasks.len()).then(|| ref_id as usize - 1)
+        };
+
+        // A task waits for its inputs, for the task its run_if looks at, and
+        // a fallback task for the task it replaces
+        let mut dependencies = Vec::with_capacity(self.tasks.len());
         for task in &self.tasks {
-            seen.insert(task.task_number);
-            if let Some(ref_id) = task.input_from_task {
-                if ref_id >= task.task_number || !seen.contains(&ref_id) {
-                    return Err(EnvelopeValidationError::BadInputReference(ref_id));
+            if let Some(retry) = &task.retry {
+                retry.validate().map_err(|error| {
+                    EnvelopeValidationError::InvalidRetry(task.task_number, error)
+                })?;
+            }
+
+            let references = match (task.input_from_task, task.inputs_from_tasks.as_slice()) {
+                (Some(_), [_, ..]) => {
+                    return Err(EnvelopeValidationError::ConflictingInputs(task.task_number));
                 }
+                (Some(ref_id), []) => vec![ref_id],
+                (None, references) => references.to_vec(),
+            };
+
+            let mut task_dependencies = Vec::with_capacity(references.len() + 1);
+            for ref_id in references {
+                let source =
+                    position(ref_id).ok_or(EnvelopeValidationError::BadInputReference(ref_id))?;
+                task_dependencies.push(source);
             }
+
+            if let Some(condition) = &task.run_if {
+                let ref_id = condition.task();
+                let source = position(ref_id)
+                    .ok_or(EnvelopeValidationError::BadConditionReference(ref_id))?;
+                condition.validate().map_err(|error| {
+                    EnvelopeValidationError::InvalidCondition(task.task_number, error)
+                })?;
+                task_dependencies.push(source);
+            }
+
+            dependencies.push(task_dependencies);
+        }
+
+        let mut has_fallback = vec![false; self.tasks.len()];
+        for (index, task) in self.tasks.iter().enumerate() {
+            if let FailurePolicy::Fallback(ref_id) = task.on_failure {
+                let fallback = position(ref_id)
+                    .ok_or(EnvelopeValidationError::BadFallbackReference(ref_id))?;
+                if std::mem::replace(&mut has_fallback[fallback], true) {
+                    return Err(EnvelopeValidationError::SharedFallback(ref_id));
+                }
+                dependencies[fallback].push(index);
+            }
+        }
+
+        topological_order(&dependencies).map_err(|index| {
+            EnvelopeValidationError::CyclicInputs(self.tasks[index].task_number)
+        })?;
+
+        plan_params::validate(
+            &self.parameters,
+            self.tasks
+                .iter()
+                .map(|task| (task.task_number, task.args.as_slice())),
+        )
+        .map_err(EnvelopeValidationError::InvalidParameters)?;
+
+        if let Some(schema) = &self.input_schema {
+            plan_params::compile_schema(schema)
+                .map_err(EnvelopeValidationError::InvalidInputSchema)?;
         }
 
         Ok(())
     }
 }
 
+/// JSON with object keys sorted at every level, so equal documents hash
+/// alike whatever order their keys were written in
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by_key(|(key, _)| *key);
+
+            out.push('{');
+            for (index, (key, value)) in fields.into_iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        serde_json::Value::Array(items) => {
+            out.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        scalar => out.push_str(&scalar.to_string()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -141,6 +357,10 @@ mod tests {
                     args: vec![],
                     timeout_secs: 300,
                     input_from_task: None,
+                    inputs_from_tasks: Vec::new(),
+                    run_if: None,
+                    on_failure: FailurePolicy::Stop,
+                    retry: None,
                 },
                 PlanStep {
                     task_number: 2,
@@ -148,17 +368,86 @@ mod tests {
                     args: vec![],
                     timeout_secs: 30,
                     input_from_task: Some(1),
+                    inputs_from_tasks: Vec::new(),
+                    run_if: None,
+                    on_failure: FailurePolicy::Stop,
+                    retry: Some(RetryPolicy {
+                        max_attempts: 3,
+                        backoff_base_ms: 500,
+                        backoff_max_ms: 5000,
+                        retryable_exit_codes: vec![75],
+                    }),
                 },
             ],
+            examples: Vec::new(),
+            parameters: Vec::new(),
+            input_schema: None,
+            schema_version: SCHEMA_VERSION.to_string(),
         };
 
-        let env =
-            JobEnvelope::from_plan(plan, "job-1".into(), "plan-1".into(), Some("desc".into()));
+        let env = JobEnvelope::from_plan(
+            plan,
+            "job-1".into(),
+            Some("plan-1".into()),
+            Some("desc".into()),
+        );
         assert_eq!(env.tasks.len(), 2);
         assert_eq!(env.tasks[0].task_number, 1);
         assert_eq!(env.tasks[1].task_number, 2);
         assert_eq!(env.tasks[1].input_from_task, Some(1));
         assert_eq!(env.tasks[1].timeout_secs, 30);
+        assert_eq!(env.tasks[1].retry.as_ref().unwrap().max_attempts, 3);
+        assert!(env.validate(10).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_retry() {
+        let mut task = job_task(1, None, &[]);
+        task.retry = serde_json::from_str(r#"{"max_attempts": 0}"#).unwrap();
+
+        assert!(matches!(
+            envelope(vec![task]).validate(10).unwrap_err(),
+            EnvelopeValidationError::InvalidRetry(1, _)
+        ));
+    }
+
+    #[test]
+    fn content_plan_ids_follow_what_the_plan_does() {
+        let plan = envelope(vec![job_task(1, None, &[]), job_task(2, Some(1), &[])]);
+        let id = plan.content_plan_id();
+        assert!(id.starts_with("plan_") && id.len() == 37, "{id}");
+
+        let mut renamed = plan.clone();
+        renamed.job_id = "another-job".to_string();
+        renamed.plan_description = Some("same tasks".to_string());
+        assert_eq!(renamed.content_plan_id(), id);
+
+        let mut changed = plan.clone();
+        changed.tasks[1].args = vec!["-r".to_string()];
+        assert_ne!(changed.content_plan_id(), id);
+
+        let mut schema_a = plan.clone();
+        schema_a.input_schema = Some(serde_json::json!({"type": "object", "required": []}));
+        let mut schema_b = plan.clone();
+        schema_b.input_schema =
+            serde_json::from_str(r#"{"required": [], "type": "object"}"#).unwrap();
+        assert_eq!(schema_a.content_plan_id(), schema_b.content_plan_id());
+        assert_ne!(schema_a.content_plan_id(), id);
+    }
+
+    #[test]
+    fn rejects_undeclared_parameters() {
+        let mut task = job_task(1, None, &[]);
+        task.args = vec!["{{input.pattern}}".to_string()];
+        let mut env = envelope(vec![task]);
+
+        assert!(matches!(
+            env.validate(10).unwrap_err(),
+            EnvelopeValidationError::InvalidParameters(_)
+        ));
+
+        env.parameters = serde_json::from_str(r#"[{"name": "pattern"}]"#).unwrap();
+        assert!(env.validate(10).is_ok());
     }
 
     #[test]
@@ -174,6 +463,10 @@ mod tests {
                     args: vec![],
                     timeout_secs: 300,
                     input_from_task: None,
+                    inputs_from_tasks: Vec::new(),
+                    run_if: None,
+                    on_failure: FailurePolicy::Stop,
+                    retry: None,
                 },
                 JobTask {
                     task_number: 3,
@@ -181,8 +474,15 @@ mod tests {
                     args: vec![],
                     timeout_secs: 300,
                     input_from_task: None,
+                    inputs_from_tasks: Vec::new(),
+                    run_if: None,
+                    on_failure: FailurePolicy::Stop,
+                    retry: None,
                 },
             ],
+            parameters: Vec::new(),
+            input_schema: None,
+            schema_version: SCHEMA_VERSION.to_string(),
         };
 
         let err = env.validate(10).unwrap_err();
@@ -202,6 +502,10 @@ mod tests {
                     args: vec![],
                     timeout_secs: 300,
                     input_from_task: None,
+                    inputs_from_tasks: Vec::new(),
+                    run_if: None,
+                    on_failure: FailurePolicy::Stop,
+                    retry: None,
                 },
                 JobTask {
                     task_number: 2,
@@ -209,11 +513,182 @@ mod tests {
                     args: vec![],
                     timeout_secs: 300,
                     input_from_task: Some(5),
+                    inputs_from_tasks: Vec::new(),
+                    run_if: None,
+                    on_failure: FailurePolicy::Stop,
+                    retry: None,
                 },
             ],
+            parameters: Vec::new(),
+            input_schema: None,
+            schema_version: SCHEMA_VERSION.to_string(),
         };
 
         let err = env.validate(10).unwrap_err();
         matches!(err, EnvelopeValidationError::BadInputReference(_));
     }
+
+    fn job_task(task_number: u32, input_from_task: Option<u32>, inputs: &[u32]) -> JobTask {
+        JobTask {
+            task_number,
+            command: "c".into(),
+            args: vec![],
+            timeout_secs: 300,
+            input_from_task,
+            inputs_from_tasks: inputs.to_vec(),
+            run_if: None,
+            on_failure: FailurePolicy::Stop,
+            retry: None,
+        }
+    }
+
+    fn envelope(tasks: Vec<JobTask>) -> JobEnvelope {
+        JobEnvelope {
+            job_id: "job".into(),
+            plan_id: "plan".into(),
+            plan_description: None,
+            tasks,
+            parameters: Vec::new(),
+            input_schema: None,
+            schema_version: SCHEMA_VERSION.to_string(),
+        }
+    }
+
+    #[test]
+    fn accepts_fan_in_and_forward_references() {
+        let env = envelope(vec![
+            job_task(1, Some(3), &[]),
+            job_task(2, None, &[]),
+            job_task(3, None, &[]),
+            job_task(4, None, &[1, 2]),
+        ]);
+
+        assert!(env.validate(10).is_ok());
+    }
+
+    #[test]
+    fn rejects_input_cycles() {
+        let env = envelope(vec![
+            job_task(1, None, &[]),
+            job_task(2, Some(3), &[]),
+            job_task(3, None, &[1, 2]),
+        ]);
+
+        let err = env.validate(10).unwrap_err();
+        assert!(matches!(err, EnvelopeValidationError::CyclicInputs(2)));
+
+        let own_output = envelope(vec![job_task(1, Some(1), &[])]);
+        assert!(matches!(
+            own_output.validate(10).unwrap_err(),
+            EnvelopeValidationError::CyclicInputs(1)
+        ));
+    }
+
+    #[test]
+    fn rejects_both_input_fields() {
+        let env = envelope(vec![job_task(1, None, &[]), job_task(2, Some(1), &[1])]);
+
+        assert!(matches!(
+            env.validate(10).unwrap_err(),
+            EnvelopeValidationError::ConflictingInputs(2)
+        ));
+    }
+
+    #[test]
+    fn reads_single_and_multiple_input_fields() {
+        let task: JobTask =
+            serde_json::from_str(r#"{"task_number": 2, "command": "sort", "input_from_task": 1}"#)
+                .unwrap();
+        assert_eq!(task.input_from_task, Some(1));
+        assert!(task.inputs_from_tasks.is_empty());
+
+        let task: JobTask = serde_json::from_str(
+            r#"{"task_number": 3, "command": "sort", "inputs_from_tasks": [1, 2]}"#,
+        )
+        .unwrap();
+        assert_eq!(task.inputs_from_tasks, vec![1, 2]);
+        assert!(!serde_json::to_string(&task)
+            .unwrap()
+            .contains(r#""input_from_task""#));
+    }
+
+    #[test]
+    fn validates_conditions_and_fallbacks() {
+        let mut primary = job_task(2, Some(1), &[]);
+        primary.run_if = Some(RunCondition::OutputMatches {
+            task: 1,
+            pattern: "ERROR".into(),
+        });
+        primary.on_failure = FailurePolicy::Fallback(3);
+
+        let env = envelope(vec![
+            job_task(1, None, &[]),
+            primary.clone(),
+            job_task(3, None, &[]),
+        ]);
+        assert!(env.validate(10).is_ok());
+
+        let mut bad_pattern = primary.clone();
+        bad_pattern.run_if = Some(RunCondition::OutputMatches {
+            task: 1,
+            pattern: "(".into(),
+        });
+        let env = envelope(vec![
+            job_task(1, None, &[]),
+            bad_pattern,
+            job_task(3, None, &[]),
+        ]);
+        assert!(matches!(
+            env.validate(10).unwrap_err(),
+            EnvelopeValidationError::InvalidCondition(2, _)
+        ));
+
+        let mut own_fallback = primary.clone();
+        own_fallback.on_failure = FailurePolicy::Fallback(2);
+        let env = envelope(vec![job_task(1, None, &[]), own_fallback]);
+        assert!(matches!(
+            env.validate(10).unwrap_err(),
+            EnvelopeValidationError::CyclicInputs(2)
+        ));
+
+        let mut unknown = primary;
+        unknown.run_if = Some(RunCondition::ExitCode { task: 9, equals: 0 });
+        let env = envelope(vec![
+            job_task(1, None, &[]),
+            unknown,
+            job_task(3, None, &[]),
+        ]);
+        assert!(matches!(
+            env.validate(10).unwrap_err(),
+            EnvelopeValidationError::BadConditionReference(9)
+        ));
+    }
+
+    #[test]
+    fn reads_conditions_and_failure_policies() {
+        let task: JobTask = serde_json::from_str(
+            r#"{
+                "task_number": 2,
+                "command": "sort",
+                "run_if": {"type": "exit_code", "task": 1, "equals": 0},
+                "on_failure": {"fallback": 3}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            task.run_if,
+            Some(RunCondition::ExitCode { task: 1, equals: 0 })
+        );
+        assert_eq!(task.on_failure, FailurePolicy::Fallback(3));
+
+        let task: JobTask = serde_json::from_str(
+            r#"{"task_number": 1, "command": "sort", "on_failure": "continue"}"#,
+        )
+        .unwrap();
+        assert_eq!(task.on_failure, FailurePolicy::Continue);
+        assert!(!serde_json::to_string(&job_task(1, None, &[]))
+            .unwrap()
+            .contains("on_failure"));
+    }
 }
@@ -6,9 +6,22 @@ pub mod job;
 pub mod logging;
 pub mod plan;
 pub mod plan_buffer;
+pub mod plan_check;
+pub mod plan_diagram;
+pub mod plan_diff;
+pub mod plan_examples;
+pub mod plan_export;
+pub mod plan_format;
+pub mod plan_graph;
+pub mod plan_import;
+pub mod plan_lint;
+pub mod plan_migrate;
+pub mod plan_params;
+pub mod plan_rewrite;
 pub mod planner;
 pub mod registry;
 pub mod repl;
+pub mod schema;
 
 use serde_json::json;
 
@@ -46,6 +59,10 @@ pub fn run() -> Result<(), String> {
         cli::Command::Plan(plan_command) => handle_plan_command(plan_command),
         cli::Command::Action(action_command) => handle_action_command(action_command),
         cli::Command::Ops(ops_command) => handle_ops_command(ops_command),
+        cli::Command::Schema(kind) => {
+            print_json(schema::schema(kind));
+            Ok(())
+        }
     }
 }
 
@@ -98,7 +115,7 @@ fn handle_plan_command(command: cli::PlanCommand) -> Result<(), String> {
                 "plan_steps": 0
             }));
         }
-        cli::PlanCommand::Validate => {
+        cli::PlanCommand::Validate { explain } => {
             let plan = storage.load()?;
 
             if plan.tasks.is_empty() {
@@ -112,19 +129,24 @@ fn handle_plan_command(command: cli::PlanCommand) -> Result<(), String> {
 
             // Run Delta validation on current plan
             let original_steps = plan.tasks.len();
-            let validated_plan = run_delta_validation(&plan, &storage)?;
+            let (validated_plan, rewrites) = run_delta_validation(&plan, &storage)?;
             let validated_steps = validated_plan.tasks.len();
 
-            // Show diff summary
-            let diff_summary = compute_plan_diff(&plan, &validated_plan);
+            // Show what Delta changed
+            let changes = diff_json(&plan_diff::diff(&plan, &validated_plan));
 
-            print_json(json!({
+            let mut output = json!({
                 "status": "ok",
                 "original_tasks": original_steps,
                 "validated_tasks": validated_steps,
-                "changes": diff_summary,
+                "changes": changes,
                 "plan_path": storage.path().display().to_string()
-            }));
+            });
+            if explain {
+                output["rewrites"] = json!(rewrites);
+            }
+
+            print_json(output);
         }
         cli::PlanCommand::Preview => {
             let plan = storage.load()?;
@@ -133,7 +155,202 @@ fn handle_plan_command(command: cli::PlanCommand) -> Result<(), String> {
                 "plan": plan
             }));
         }
-        cli::PlanCommand::Submit { json } => {
+        cli::PlanCommand::Run {
+            input,
+            params,
+            report,
+            jobs,
+            resume,
+            cache,
+        } => {
+            let plan = storage.load()?;
+
+            if plan.tasks.is_empty() {
+                return Err("plan is empty. Use `PLAN add` to generate tasks first.".to_string());
+            }
+
+            let plan = resolve_params(&plan, params.as_deref())?;
+
+            let input = match input {
+                Some(path) => executor::ExecutionInput::File(path.into()),
+                None if input::InputCollector::stdin_is_terminal() => {
+                    executor::ExecutionInput::Empty
+                }
+                None => executor::ExecutionInput::Stdin,
+            };
+
+            logging::info(&format!(
+                "PLAN run request with {} task(s); input: {:?}",
+                plan.tasks.len(),
+                input
+            ));
+
+            let registry = registry::ToolRegistry::new();
+            let mut executor = executor::Executor::new().with_resume(resume);
+            if let Some(jobs) = jobs {
+                executor = executor.with_jobs(jobs);
+            }
+            // Keying the cache needs a digest of the whole input, which stops
+            // STDIN from streaming, so it is only used when asked for
+            if cache || resume {
+                executor = executor.with_cache(executor::TaskCache::default_location()?);
+            }
+
+            match report {
+                None => executor.execute(&plan, &input, &registry)?,
+                Some(destination) => {
+                    let run_report = executor.run(&plan, &input, &registry, &mut std::io::stdout());
+                    write_run_report(&run_report, &destination)?;
+                    run_report.into_result()?;
+                }
+            }
+        }
+        cli::PlanCommand::Test { file, json, params } => {
+            let (plan, plan_path) = match &file {
+                Some(file) => {
+                    let path = std::path::PathBuf::from(file);
+                    (plan_format::load_plan_file(&path)?, path)
+                }
+                None => (storage.load()?, storage.path().to_path_buf()),
+            };
+
+            if plan.examples.is_empty() {
+                return Err(
+                    "plan has no examples. Add `examples` to the plan file to test it.".to_string(),
+                );
+            }
+
+            // Example paths are relative to the plan file, which the
+            // temporary buffer is not
+            if file.is_none() && storage.is_temporary() && has_relative_paths(&plan) {
+                return Err(format!(
+                    "example paths are relative to the plan file, but the buffer is the temporary file {}; run `PLAN test <file>` on the plan file, or point AGX_PLAN_PATH at it",
+                    storage.path().display()
+                ));
+            }
+
+            let base_dir = plan_path
+                .parent()
+                .map(std::path::Path::to_path_buf)
+                .unwrap_or_default();
+
+            logging::info(&format!(
+                "PLAN test request with {} example(s)",
+                plan.examples.len()
+            ));
+
+            let registry = registry::ToolRegistry::new();
+            let values = parse_params(params.as_deref())?;
+            let results = plan_examples::run_examples(&plan, values.as_ref(), &base_dir, &registry)
+                .map_err(|error| params_error(&plan, values.is_some(), error))?;
+            let failed = results.iter().filter(|result| !result.passed).count();
+
+            if json {
+                print_json(json!({
+                    "status": if failed == 0 { "ok" } else { "failed" },
+                    "passed": results.len() - failed,
+                    "failed": failed,
+                    "examples": results
+                }));
+            } else {
+                for result in &results {
+                    if result.passed {
+                        println!("✅ {} passed", result.name);
+                    } else {
+                        println!("❌ {} failed", result.name);
+                        for failure in &result.failures {
+                            println!("   {failure}");
+                        }
+                    }
+                }
+
+                println!(
+                    "{} example(s): {} passed, {} failed",
+                    results.len(),
+                    results.len() - failed,
+                    failed
+                );
+            }
+
+            if failed > 0 {
+                return Err(format!("{failed} of {} example(s) failed", results.len()));
+            }
+        }
+        cli::PlanCommand::Check { json, params } => {
+            let plan = resolve_params(&storage.load()?, params.as_deref())?;
+
+            logging::info(&format!(
+                "PLAN check request with {} task(s)",
+                plan.tasks.len()
+            ));
+
+            let check = plan_check::check_plan(&plan, &registry::ToolRegistry::new());
+
+            if json {
+                print_json(json!({
+                    "status": if check.is_ok() { "ok" } else { "failed" },
+                    "tasks": check.tasks,
+                    "errors": check.errors
+                }));
+            } else {
+                for task in &check.tasks {
+                    let input = task.input.as_deref().unwrap_or("?");
+                    let mark = if task.errors.is_empty() { "✅" } else { "❌" };
+
+                    println!(
+                        "{mark} task {} ({input}): {}",
+                        task.task_number,
+                        task.command_line()
+                    );
+                    for error in &task.errors {
+                        println!("   {error}");
+                    }
+                }
+
+                for error in &check.errors {
+                    println!("❌ {error}");
+                }
+            }
+
+            if !check.is_ok() {
+                return Err(format!(
+                    "plan check found {} problem(s)",
+                    check.error_count()
+                ));
+            }
+        }
+        cli::PlanCommand::Lint { json } => {
+            let plan = storage.load()?;
+
+            logging::info(&format!(
+                "PLAN lint request with {} task(s)",
+                plan.tasks.len()
+            ));
+
+            let findings = plan_lint::lint(&plan, &registry::ToolRegistry::new());
+            let errors = plan_lint::count(&findings, plan_lint::Severity::Error);
+
+            if json {
+                print_json(json!({
+                    "status": if errors == 0 { "ok" } else { "failed" },
+                    "findings": findings,
+                    "summary": plan_lint::summary(&findings)
+                }));
+            } else {
+                for finding in &findings {
+                    println!("{finding}");
+                }
+                if !findings.is_empty() {
+                    println!();
+                }
+                println!("{}", plan_lint::summary(&findings));
+            }
+
+            if errors > 0 {
+                return Err(format!("plan lint found {errors} error(s)"));
+            }
+        }
+        cli::PlanCommand::Submit { json, force } => {
             let mut plan = storage.load()?;
 
             logging::info(&format!(
@@ -144,13 +361,15 @@ fn handle_plan_command(command: cli::PlanCommand) -> Result<(), String> {
             // Auto-validate with Delta if AGX_AUTO_VALIDATE is set
             if should_auto_validate() {
                 logging::info("Auto-validation enabled, running Delta validation before submit");
-                plan = run_delta_validation(&plan, &storage)?;
+                plan = run_delta_validation(&plan, &storage)?.0;
                 logging::info(&format!(
                     "Auto-validation complete: {} task(s)",
                     plan.tasks.len()
                 ));
             }
 
+            check_lint_errors(&plan, force)?;
+
             let job = build_job_envelope(plan)?;
             let plan_id = job.plan_id.clone();
             let task_count = job.tasks.len();
@@ -160,6 +379,27 @@ fn handle_plan_command(command: cli::PlanCommand) -> Result<(), String> {
             let agq_config = agq_client::AgqConfig::from_env();
             let client = agq_client::AgqClient::new(agq_config);
 
+            if let Some(existing) = existing_plan(&client, &plan_id) {
+                if json {
+                    print_json(json!({
+                        "plan_id": plan_id,
+                        "task_count": existing.task_count,
+                        "status": "exists"
+                    }));
+                } else {
+                    println!(
+                        "ℹ️  An identical plan is already stored in AGQ; not submitting it again"
+                    );
+                    println!("   Plan ID: {}", plan_id);
+                    if let Some(created_at) = existing.created_at {
+                        println!("   Created: {}", created_at);
+                    }
+                    println!();
+                    println!("Use with: agx ACTION submit --plan-id {}", plan_id);
+                }
+                return Ok(());
+            }
+
             match client.submit_plan(&job_json) {
                 Ok(submission) => {
                     let metadata = plan_buffer::PlanMetadata {
@@ -192,7 +432,10 @@ fn handle_plan_command(command: cli::PlanCommand) -> Result<(), String> {
                 }
             }
         }
-        cli::PlanCommand::Add { instruction } => {
+        cli::PlanCommand::Add {
+            instruction,
+            explain,
+        } => {
             let input = collect_planner_input()?;
             logging::info(&format!(
                 "instruction: {}; bytes: {}; lines: {}; binary: {}",
@@ -212,26 +455,12 @@ fn handle_plan_command(command: cli::PlanCommand) -> Result<(), String> {
             logging::info(&format!("planner raw output: {}", plan_output.raw_json));
 
             let parsed = plan_output.parse()?;
-            let executable_plan = parsed.normalize_for_execution();
+            let (executable_plan, rewrites) = parsed.normalize_for_execution();
+            log_rewrites(&rewrites);
             let added_tasks = executable_plan.tasks.len();
 
             let mut buffer = storage.load()?;
-            let offset = buffer.tasks.len() as u32;
-            buffer.tasks.extend(executable_plan.tasks.into_iter());
-
-            // Renumber newly added tasks by offset and adjust their input_from_task references
-            // Existing tasks keep their numbers unchanged
-            if offset > 0 {
-                for task in buffer.tasks.iter_mut().skip(offset as usize) {
-                    let old_number = task.task_number;
-                    task.task_number = old_number + offset;
-
-                    // Adjust input_from_task references within newly added tasks
-                    if let Some(old_ref) = task.input_from_task {
-                        task.input_from_task = Some(old_ref + offset);
-                    }
-                }
-            }
+            append_plan(&mut buffer, executable_plan)?;
 
             logging::info(&format!(
                 "PLAN add appended {added_tasks} task(s); buffer now has {} task(s)",
@@ -240,12 +469,17 @@ fn handle_plan_command(command: cli::PlanCommand) -> Result<(), String> {
 
             storage.save(&buffer)?;
 
-            print_json(json!({
+            let mut output = json!({
                 "status": "ok",
                 "added_tasks": added_tasks,
                 "total_tasks": buffer.tasks.len(),
                 "plan_path": storage.path().display().to_string()
-            }));
+            });
+            if explain {
+                output["rewrites"] = json!(rewrites);
+            }
+
+            print_json(output);
         }
         cli::PlanCommand::List { json } => {
             let agq_config = agq_client::AgqConfig::from_env();
@@ -297,6 +531,86 @@ fn handle_plan_command(command: cli::PlanCommand) -> Result<(), String> {
                 }
             }
         }
+        cli::PlanCommand::Import { source } => {
+            let plan = match &source {
+                cli::ImportSource::Pipeline(pipeline) => {
+                    plan_import::import_pipeline(pipeline, &registry::ToolRegistry::new())?
+                }
+                cli::ImportSource::File(path) => {
+                    plan_format::load_plan_file(std::path::Path::new(path))?
+                }
+            };
+
+            let source = match source {
+                cli::ImportSource::Pipeline(pipeline) => format!("`{pipeline}`"),
+                cli::ImportSource::File(path) => path,
+            };
+            logging::info(&format!(
+                "PLAN import of {source} produced {} task(s)",
+                plan.tasks.len()
+            ));
+
+            storage.save(&plan)?;
+
+            print_json(json!({
+                "status": "ok",
+                "imported_tasks": plan.tasks.len(),
+                "plan_path": storage.path().display().to_string()
+            }));
+        }
+        cli::PlanCommand::Export { format, params } => {
+            let plan = storage.load()?;
+            // A plan file keeps its placeholders; a script runs as is
+            let plan = match format {
+                plan_export::ExportFormat::Sh => resolve_params(&plan, params.as_deref())?,
+                plan_export::ExportFormat::Plan(_) => plan,
+            };
+            let exported = plan_export::export(&plan, format, &registry::ToolRegistry::new())?;
+
+            print!("{exported}");
+        }
+        cli::PlanCommand::Graph { plan_id, format } => {
+            let plan = match plan_id {
+                Some(plan_id) => agq_client::AgqClient::new(agq_client::AgqConfig::from_env())
+                    .get_plan(&plan_id)
+                    .map_err(|error| format!("failed to get plan: {}", error))?,
+                None => storage.load()?,
+            };
+
+            print!("{}", plan_diagram::render(&plan, format)?);
+        }
+        cli::PlanCommand::Diff {
+            before,
+            after,
+            json,
+        } => {
+            let diff = plan_diff::diff(&load_plan_source(&before)?, &load_plan_source(&after)?);
+
+            if json {
+                print_json(json!({
+                    "before": before,
+                    "after": after,
+                    "changes": diff_json(&diff)
+                }));
+            } else {
+                print!("{}", diff);
+            }
+        }
+        cli::PlanCommand::Migrate { path } => {
+            match plan_migrate::migrate_file(std::path::Path::new(&path))? {
+                Some(from) => println!(
+                    "✅ Migrated {} from schema_version {} to {}",
+                    path,
+                    from,
+                    plan::SCHEMA_VERSION
+                ),
+                None => println!(
+                    "{} is already at schema_version {}",
+                    path,
+                    plan::SCHEMA_VERSION
+                ),
+            }
+        }
     }
 
     Ok(())
@@ -313,7 +627,7 @@ fn collect_planner_input() -> Result<input::InputSummary, String> {
 fn enforce_instruction_limit(command: &cli::PlanCommand) -> Result<(), String> {
     const MAX_INSTRUCTION_BYTES: usize = 8 * 1024;
 
-    if let cli::PlanCommand::Add { instruction } = command {
+    if let cli::PlanCommand::Add { instruction, .. } = command {
         if instruction.len() > MAX_INSTRUCTION_BYTES {
             return Err(format!(
                 "instruction is too long ({} bytes > {} allowed)",
@@ -326,6 +640,141 @@ fn enforce_instruction_limit(command: &cli::PlanCommand) -> Result<(), String> {
     Ok(())
 }
 
+/// Append the tasks of `plan`, numbered from 1, to `buffer`, so that what read
+/// the plan input now reads the buffer's last output. Once either side
+/// declares references, the implicit links are written out, since a task
+/// without a reference in an explicit plan reads the plan input instead.
+pub(crate) fn append_plan(
+    buffer: &mut plan::WorkflowPlan,
+    plan: plan::WorkflowPlan,
+) -> Result<(), String> {
+    let linked = |tasks: &[plan::PlanStep]| {
+        tasks
+            .iter()
+            .any(|task| task.input_from_task.is_some() || !task.inputs_from_tasks.is_empty())
+    };
+
+    let offset = buffer.tasks.len() as u32;
+    let explicit = offset > 0 && (linked(&buffer.tasks) || linked(&plan.tasks));
+    let appended = plan_graph::PlanGraph::build(&plan)?;
+    let reads_plan_input: Vec<bool> = (0..plan.tasks.len())
+        .map(|index| appended.reads_plan_input(index))
+        .collect();
+    let chained = !linked(&plan.tasks);
+
+    if explicit && !linked(&buffer.tasks) {
+        for index in 1..buffer.tasks.len() {
+            buffer.tasks[index].input_from_task = Some(buffer.tasks[index - 1].task_number);
+        }
+    }
+    let sink = plan_graph::PlanGraph::build(buffer)?
+        .sinks()
+        .last()
+        .map(|&index| buffer.tasks[index].task_number);
+
+    buffer.tasks.extend(plan.tasks);
+
+    // Renumber newly added tasks by offset and adjust their references
+    // Existing tasks keep their numbers unchanged
+    if offset > 0 {
+        for task in buffer.tasks.iter_mut().skip(offset as usize) {
+            task.task_number += offset;
+
+            if let Some(old_ref) = task.input_from_task {
+                task.input_from_task = Some(old_ref + offset);
+            }
+            for old_ref in task.inputs_from_tasks.iter_mut() {
+                *old_ref += offset;
+            }
+            if let Some(condition) = task.run_if.as_mut() {
+                condition.offset_task(offset);
+            }
+            if let plan::FailurePolicy::Fallback(old_ref) = task.on_failure {
+                task.on_failure = plan::FailurePolicy::Fallback(old_ref + offset);
+            }
+        }
+    }
+
+    if explicit {
+        for (index, reads_plan_input) in reads_plan_input.into_iter().enumerate() {
+            let position = offset as usize + index;
+            if reads_plan_input {
+                buffer.tasks[position].input_from_task = sink;
+            } else if chained {
+                buffer.tasks[position].input_from_task =
+                    Some(buffer.tasks[position - 1].task_number);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// `plan` with its placeholders filled in from the `--params` JSON object,
+/// or from the parameter defaults
+fn resolve_params(
+    plan: &plan::WorkflowPlan,
+    params: Option<&str>,
+) -> Result<plan::WorkflowPlan, String> {
+    let values = parse_params(params)?;
+
+    plan_params::resolve_plan(plan, values.as_ref())
+        .map_err(|error| params_error(plan, values.is_some(), error))
+}
+
+fn parse_params(params: Option<&str>) -> Result<Option<serde_json::Value>, String> {
+    params
+        .map(serde_json::from_str::<serde_json::Value>)
+        .transpose()
+        .map_err(|error| format!("invalid --params JSON: {error}"))
+}
+
+/// Point at `--params` when the defaults alone did not fill `plan` in
+fn params_error(plan: &plan::WorkflowPlan, given: bool, error: String) -> String {
+    if !given && !plan.parameters.is_empty() {
+        format!("{error}; pass parameter values with --params")
+    } else {
+        error
+    }
+}
+
+/// Whether an example of `plan` names a file by a relative path
+fn has_relative_paths(plan: &plan::WorkflowPlan) -> bool {
+    plan.examples.iter().any(|example| {
+        std::iter::once(&example.input)
+            .chain(&example.expected_output)
+            .any(|path| std::path::Path::new(path).is_relative())
+    })
+}
+
+/// Refuse to submit `plan` while it has lint errors, unless `force` is set
+pub(crate) fn check_lint_errors(plan: &plan::WorkflowPlan, force: bool) -> Result<(), String> {
+    let findings = plan_lint::lint(plan, &registry::ToolRegistry::new());
+    let errors = plan_lint::count(&findings, plan_lint::Severity::Error);
+
+    if errors == 0 {
+        return Ok(());
+    }
+
+    if !force {
+        let listed: Vec<String> = findings
+            .iter()
+            .filter(|finding| finding.severity == plan_lint::Severity::Error)
+            .map(|finding| format!("  {finding}"))
+            .collect();
+        return Err(format!(
+            "plan has {errors} lint error(s); fix them or pass --force:\n{}",
+            listed.join("\n")
+        ));
+    }
+
+    logging::info(&format!(
+        "submitting despite {errors} lint error(s) (--force)"
+    ));
+
+    Ok(())
+}
+
 fn should_auto_validate() -> bool {
     match std::env::var("AGX_AUTO_VALIDATE") {
         Ok(value) => {
@@ -336,48 +785,59 @@ fn should_auto_validate() -> bool {
     }
 }
 
-fn compute_plan_diff(
-    original: &plan::WorkflowPlan,
-    validated: &plan::WorkflowPlan,
-) -> serde_json::Value {
-    let original_cmds: Vec<String> = original.tasks.iter().map(|t| t.command.clone()).collect();
-    let validated_cmds: Vec<String> = validated.tasks.iter().map(|t| t.command.clone()).collect();
-
-    let added: Vec<String> = validated_cmds
-        .iter()
-        .filter(|cmd| !original_cmds.contains(cmd))
-        .cloned()
-        .collect();
+fn random_plan_ids() -> bool {
+    match std::env::var("AGX_RANDOM_PLAN_ID") {
+        Ok(value) => {
+            let normalized = value.to_lowercase();
+            matches!(normalized.as_str(), "1" | "true" | "yes" | "on")
+        }
+        Err(_) => false,
+    }
+}
 
-    let removed: Vec<String> = original_cmds
-        .iter()
-        .filter(|cmd| !validated_cmds.contains(cmd))
-        .cloned()
-        .collect();
+/// The plan AGQ already stores under `plan_id`, if any. Failing to list the
+/// plans is not fatal: the submit that follows reports connection problems.
+fn existing_plan(client: &agq_client::AgqClient, plan_id: &str) -> Option<agq_client::PlanSummary> {
+    match client.list_plans() {
+        Ok(plans) => plans.into_iter().find(|plan| plan.plan_id == plan_id),
+        Err(error) => {
+            logging::info(&format!(
+                "could not check AGQ for an identical plan: {error}"
+            ));
+            None
+        }
+    }
+}
 
-    let task_count_change = validated.tasks.len() as i32 - original.tasks.len() as i32;
+/// A plan from a plan file, or from AGQ when `source` is not a file
+fn load_plan_source(source: &str) -> Result<plan::WorkflowPlan, String> {
+    let path = std::path::Path::new(source);
 
-    json!({
-        "added": added,
-        "removed": removed,
-        "task_count_change": task_count_change,
-        "summary": if task_count_change > 0 {
-            format!("Added {} task(s)", task_count_change)
-        } else if task_count_change < 0 {
-            format!("Removed {} task(s)", -task_count_change)
-        } else {
-            "No change in task count".to_string()
-        }
+    if path.is_file() {
+        return plan_format::load_plan_file(path);
+    }
+
+    let client = agq_client::AgqClient::new(agq_client::AgqConfig::from_env());
+    client.get_plan(source).map_err(|error| {
+        format!("`{source}` is not a plan file, and getting it from AGQ failed: {error}")
     })
 }
 
+fn diff_json(diff: &plan_diff::PlanDiff) -> serde_json::Value {
+    let mut value = serde_json::to_value(diff).unwrap_or_else(|_| json!({}));
+    value["summary"] = json!(diff.summary());
+    value
+}
+
 /// Instruction used for Delta validation
 const DELTA_VALIDATION_INSTRUCTION: &str = "Validate and refine this plan";
 
+/// Validate `current_plan` with Delta and save the result to the buffer.
+/// Also returns the rewrite rules applied to Delta's output.
 fn run_delta_validation(
     current_plan: &plan::WorkflowPlan,
     storage: &plan_buffer::PlanStorage,
-) -> Result<plan::WorkflowPlan, String> {
+) -> Result<(plan::WorkflowPlan, Vec<plan_rewrite::Fired>), String> {
     // Create Delta planner with explicit ModelRole (no env var mutation)
     let delta_config = planner::PlannerConfig::for_delta()
         .map_err(|e| format!("Failed to create Delta config: {}", e))?;
@@ -399,7 +859,12 @@ fn run_delta_validation(
     logging::info(&format!("Delta validation output: {}", plan_output.raw_json));
 
     let parsed = plan_output.parse()?;
-    let validated_plan = parsed.normalize_for_execution();
+    let (mut validated_plan, rewrites) = parsed.normalize_for_execution();
+    log_rewrites(&rewrites);
+
+    // Examples describe what the plan must keep producing; they survive
+    // validation so `PLAN test` can check the validated plan
+    validated_plan.examples = current_plan.examples.clone();
 
     // Save validated plan to buffer
     storage.save(&validated_plan)?;
@@ -409,12 +874,25 @@ fn run_delta_validation(
         validated_plan.tasks.len()
     ));
 
-    Ok(validated_plan)
+    Ok((validated_plan, rewrites))
 }
 
+pub(crate) fn log_rewrites(rewrites: &[plan_rewrite::Fired]) {
+    for fired in rewrites {
+        logging::info(&format!(
+            "rewrite rule {} applied {} time(s): {}",
+            fired.rule, fired.count, fired.description
+        ));
+    }
+}
+
+/// Wrap `plan` into a job envelope. Unless the plan names its own plan_id,
+/// the ID is derived from the plan's content, so submitting the same plan
+/// twice yields the same ID; `AGX_RANDOM_PLAN_ID` opts out.
 pub fn build_job_envelope(plan: plan::WorkflowPlan) -> Result<job::JobEnvelope, String> {
     let job_id = uuid::Uuid::new_v4().to_string();
-    let plan_id = uuid::Uuid::new_v4().to_string();
+    let plan_id =
+        (plan.plan_id.is_none() && random_plan_ids()).then(|| uuid::Uuid::new_v4().to_string());
     let plan_description = std::env::var("AGX_PLAN_DESCRIPTION").ok();
 
     let envelope = job::JobEnvelope::from_plan(
@@ -424,7 +902,7 @@ pub fn build_job_envelope(plan: plan::WorkflowPlan) -> Result<job::JobEnvelope,
         plan_description.filter(|s| !s.is_empty()),
     );
     envelope
-        .validate(100)
+        .validate(job::MAX_TASKS)
         .map_err(|e| format!("job envelope validation failed: {e:?}"))?;
 
     Ok(envelope)
@@ -494,7 +972,7 @@ fn handle_action_command(command: cli::ActionCommand) -> Result<(), String> {
 
             logging::info(&format!("Retrieving plan: {}", plan_id));
 
-            let _plan = client.get_plan(&plan_id).map_err(|e| {
+            let plan = client.get_plan(&plan_id).map_err(|e| {
                 if e.contains("AGQ error") {
                     format!("Error: Plan '{}' not found", plan_id)
                 } else {
@@ -541,28 +1019,62 @@ fn handle_action_command(command: cli::ActionCommand) -> Result<(), String> {
                 serde_json::json!([])
             };
 
+            // Step 4: Check every input against the plan's input_schema and
+            // fill in the placeholders of a parameterized plan, so bad inputs
+            // fail here rather than on a worker
+            if let (Some(schema), Some(inputs)) = (&plan.input_schema, inputs_array.as_array()) {
+                plan_params::check_inputs(schema, inputs)
+                    .map_err(|e| format!("Error: Invalid input:\n{}", e))?;
+            }
+
+            plan_params::validate_plan(&plan)
+                .map_err(|e| format!("Error: Plan '{}' has invalid parameters: {}", plan_id, e))?;
+
+            // Without inputs, the defaults must cover every parameter
+            if inputs_array.as_array().is_some_and(Vec::is_empty) {
+                plan_params::resolve(&plan, &serde_json::json!({}))
+                    .map_err(|e| format!("Error: {}; pass it with --input or --inputs-file", e))?;
+            }
+
+            let resolved_tasks = match inputs_array.as_array() {
+                Some(inputs) if !plan.parameters.is_empty() => Some(
+                    inputs
+                        .iter()
+                        .enumerate()
+                        .map(|(index, input)| {
+                            plan_params::resolve(&plan, input)
+                                .map_err(|e| format!("Error: inputs[{}]: {}", index, e))
+                        })
+                        .collect::<Result<Vec<_>, String>>()?,
+                ),
+                _ => None,
+            };
+
             logging::info(&format!(
                 "ACTION submit request for plan_id: {}",
                 plan_id
             ));
 
-            // Step 4: Generate action_id
+            // Step 5: Generate action_id
             let action_id = format!("action_{}", uuid::Uuid::new_v4().simple());
 
-            // Step 5: Build ACTION.SUBMIT payload
-            let action_request = serde_json::json!({
+            // Step 6: Build ACTION.SUBMIT payload
+            let mut action_request = serde_json::json!({
                 "action_id": action_id,
                 "plan_id": plan_id,
                 "inputs": inputs_array,
             });
+            if let Some(resolved_tasks) = resolved_tasks {
+                action_request["resolved_tasks"] = serde_json::json!(resolved_tasks);
+            }
 
             let action_json = serde_json::to_string(&action_request)
                 .map_err(|e| format!("failed to serialize action request: {}", e))?;
 
-            // Step 6: Submit to AGQ
+            // Step 7: Submit to AGQ
             match client.submit_action(&action_json) {
                 Ok(response) => {
-                    // Step 7: Display result
+                    // Step 8: Display result
                     if json {
                         print_json(serde_json::json!({
                             "job_id": response.job_ids.first().cloned().unwrap_or_default(),
@@ -632,6 +1144,21 @@ fn handle_ops_command(command: cli::OpsCommand) -> Result<(), String> {
     Ok(())
 }
 
+/// Write the run report to `destination`, or to STDERR for `-` so that it
+/// never mixes with the plan output on STDOUT
+fn write_run_report(report: &executor::ExecutionReport, destination: &str) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(report)
+        .map_err(|error| format!("failed to serialize run report: {error}"))?;
+
+    if destination == "-" {
+        eprintln!("{text}");
+        return Ok(());
+    }
+
+    std::fs::write(destination, text + "\n")
+        .map_err(|error| format!("failed to write run report to {destination}: {error}"))
+}
+
 fn print_json(value: serde_json::Value) {
     match serde_json::to_string_pretty(&value) {
         Ok(json_text) => println!("{json_text}"),
@@ -642,12 +1169,14 @@ fn print_json(value: serde_json::Value) {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::plan::step;
 
     #[test]
     fn enforce_instruction_limit_rejects_large() {
         let long_instruction = "x".repeat(9 * 1024);
         let command = cli::PlanCommand::Add {
             instruction: long_instruction,
+            explain: false,
         };
 
         let result = enforce_instruction_limit(&command);
@@ -666,6 +1195,10 @@ mod tests {
                     args: vec![],
                     timeout_secs: 300,
                     input_from_task: None,
+                    inputs_from_tasks: Vec::new(),
+                    run_if: None,
+                    on_failure: plan::FailurePolicy::Stop,
+                    retry: None,
                 },
                 plan::PlanStep {
                     task_number: 2,
@@ -673,84 +1206,113 @@ mod tests {
                     args: vec![],
                     timeout_secs: 300,
                     input_from_task: Some(1),
+                    inputs_from_tasks: Vec::new(),
+                    run_if: None,
+                    on_failure: plan::FailurePolicy::Stop,
+                    retry: None,
                 },
             ],
+            examples: Vec::new(),
+            parameters: Vec::new(),
+            input_schema: None,
+            schema_version: plan::SCHEMA_VERSION.to_string(),
         };
 
-        let env = build_job_envelope(plan).expect("envelope should build");
+        let env = build_job_envelope(plan.clone()).expect("envelope should build");
         assert_eq!(env.tasks.len(), 2);
         assert!(!env.job_id.is_empty());
-        assert!(!env.plan_id.is_empty());
+        assert!(env.plan_id.starts_with("plan_"));
+
+        let again = build_job_envelope(plan).expect("envelope should build");
+        assert_eq!(again.plan_id, env.plan_id);
+        assert_ne!(again.job_id, env.job_id);
     }
 
     #[test]
     fn plan_append_preserves_task_dependencies() {
-        // Test that appending new tasks preserves input_from_task references
-        // Simulates PLAN add workflow where new tasks are appended to existing buffer
-
         // Existing buffer with dependencies: task 2 depends on task 1
         let mut buffer = plan::WorkflowPlan {
-            plan_id: None,
-            plan_description: None,
+            tasks: vec![step(1, "cat", &[], None), step(2, "sort", &[], Some(1))],
+            ..Default::default()
+        };
+
+        // New plan to append (normalized, so starts at 1)
+        let new_plan = plan::WorkflowPlan {
             tasks: vec![
+                step(1, "grep", &[], None),
+                step(2, "tee", &[], Some(1)),
                 plan::PlanStep {
-                    task_number: 1,
-                    command: "cat".into(),
-                    args: vec![],
-                    timeout_secs: 300,
-                    input_from_task: None,
-                },
-                plan::PlanStep {
-                    task_number: 2,
-                    command: "sort".into(),
-                    args: vec![],
-                    timeout_secs: 300,
-                    input_from_task: Some(1), // Depends on task 1
+                    inputs_from_tasks: vec![1, 2],
+                    run_if: Some(plan::RunCondition::OutputNotEmpty { task: 2 }),
+                    on_failure: plan::FailurePolicy::Fallback(4),
+                    ..step(3, "wc", &[], None)
                 },
+                step(4, "head", &[], Some(1)),
             ],
+            ..Default::default()
         };
 
-        // New plan to append (normalized, so starts at 1)
-        let new_plan = plan::WorkflowPlan {
-            plan_id: None,
-            plan_description: None,
-            tasks: vec![plan::PlanStep {
-                task_number: 1,
-                command: "uniq".into(),
-                args: vec![],
-                timeout_secs: 300,
-                input_from_task: None,
-            }],
-        };
+        append_plan(&mut buffer, new_plan).unwrap();
 
-        // Simulate PLAN add logic
-        let offset = buffer.tasks.len() as u32;
-        buffer.tasks.extend(new_plan.tasks.into_iter());
+        let numbers: Vec<u32> = buffer.tasks.iter().map(|task| task.task_number).collect();
+        assert_eq!(numbers, [1, 2, 3, 4, 5, 6]);
 
-        if offset > 0 {
-            for task in buffer.tasks.iter_mut().skip(offset as usize) {
-                let old_number = task.task_number;
-                task.task_number = old_number + offset;
+        // Existing tasks are untouched
+        assert_eq!(buffer.tasks[0].input_from_task, None);
+        assert_eq!(buffer.tasks[1].input_from_task, Some(1));
+
+        // References within the new tasks follow the renumbering
+        assert_eq!(buffer.tasks[2].input_from_task, Some(2));
+        assert_eq!(buffer.tasks[3].input_from_task, Some(3));
+        assert_eq!(buffer.tasks[4].inputs_from_tasks, [3, 4]);
+        assert_eq!(
+            buffer.tasks[4].run_if,
+            Some(plan::RunCondition::OutputNotEmpty { task: 4 })
+        );
+        assert_eq!(buffer.tasks[4].on_failure, plan::FailurePolicy::Fallback(6));
+        assert_eq!(buffer.tasks[5].input_from_task, Some(3));
+    }
 
-                if let Some(old_ref) = task.input_from_task {
-                    task.input_from_task = Some(old_ref + offset);
-                }
+    #[test]
+    fn plan_append_continues_explicit_pipelines() {
+        fn plan(tasks: Vec<plan::PlanStep>) -> plan::WorkflowPlan {
+            plan::WorkflowPlan {
+                tasks,
+                ..Default::default()
             }
         }
+        fn inputs(plan: &plan::WorkflowPlan) -> Vec<Option<u32>> {
+            plan.tasks.iter().map(|task| task.input_from_task).collect()
+        }
 
-        // Verify results
-        assert_eq!(buffer.tasks.len(), 3);
-        assert_eq!(buffer.tasks[0].task_number, 1);
-        assert_eq!(buffer.tasks[0].command, "cat");
-        assert_eq!(buffer.tasks[0].input_from_task, None);
-
+        // As left by PLAN import --pipeline
+        let mut buffer = plan(vec![
+            step(1, "grep", &[], None),
+            step(2, "sort", &[], Some(1)),
+        ]);
+        append_plan(
+            &mut buffer,
+            plan(vec![step(1, "uniq", &[], None), step(2, "head", &[], None)]),
+        )
+        .unwrap();
+        assert_eq!(inputs(&buffer), [None, Some(1), Some(2), Some(3)]);
+        let graph = plan_graph::PlanGraph::build(&buffer).unwrap();
+        assert_eq!(graph.sinks(), [3]);
+
+        // An explicit addition to a linear buffer
+        let mut buffer = plan(vec![step(1, "grep", &[], None), step(2, "sort", &[], None)]);
+        append_plan(
+            &mut buffer,
+            plan(vec![step(1, "tee", &[], None), step(2, "wc", &[], Some(1))]),
+        )
+        .unwrap();
+        assert_eq!(inputs(&buffer), [None, Some(1), Some(2), Some(3)]);
+
+        // Linear plans stay implicit
+        let mut buffer = plan(vec![step(1, "grep", &[], None)]);
+        append_plan(&mut buffer, plan(vec![step(1, "sort", &[], None)])).unwrap();
+        assert_eq!(inputs(&buffer), [None, None]);
         assert_eq!(buffer.tasks[1].task_number, 2);
-        assert_eq!(buffer.tasks[1].command, "sort");
-        assert_eq!(buffer.tasks[1].input_from_task, Some(1)); // Still points to task 1 ✓
-
-        assert_eq!(buffer.tasks[2].task_number, 3); // Renumbered from 1 to 3
-        assert_eq!(buffer.tasks[2].command, "uniq");
-        assert_eq!(buffer.tasks[2].input_from_task, None);
     }
 
     #[test]
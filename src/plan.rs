@@ -1,15 +1,32 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Version of the plan schema this build reads and writes. Older documents
+/// are upgraded by `plan_migrate`.
+pub const SCHEMA_VERSION: &str = "0.2";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct WorkflowPlan {
+    #[serde(default = "schema_version")]
+    pub schema_version: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub plan_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub plan_description: Option<String>,
     pub tasks: Vec<PlanStep>,
+    /// Regression cases checked by `PLAN test`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub examples: Vec<PlanExample>,
+    /// Values `{{input.<name>}}` placeholders in task args are resolved
+    /// from at `ACTION submit`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<PlanParameter>,
+    /// JSON Schema every input of `ACTION submit` must satisfy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_schema: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct PlanStep {
     pub task_number: u32,
     pub command: String,
@@ -19,60 +36,281 @@ pub struct PlanStep {
     pub timeout_secs: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub input_from_task: Option<u32>,
+    /// Tasks whose outputs are concatenated, in this order, into STDIN
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs_from_tasks: Vec<u32>,
+    /// Run only when this holds; otherwise the task is skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_if: Option<RunCondition>,
+    #[serde(default, skip_serializing_if = "FailurePolicy::is_stop")]
+    pub on_failure: FailurePolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
 }
 
 fn default_timeout() -> u32 {
     300
 }
 
-#[derive(Debug, Deserialize)]
-struct SimpleWorkflowPlan {
-    plan: Vec<String>,
+pub(crate) fn schema_version() -> String {
+    SCHEMA_VERSION.to_string()
+}
+
+/// Condition on the outcome of an earlier task. `exit_code` compares the
+/// exit code the task ended with, even when it failed; it does not hold for
+/// a task that was skipped or timed out, which has none. The output
+/// conditions do not hold for a task that was skipped or failed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RunCondition {
+    ExitCode {
+        task: u32,
+        equals: i32,
+    },
+    OutputEmpty {
+        task: u32,
+    },
+    OutputNotEmpty {
+        task: u32,
+    },
+    /// Regular expression searched for anywhere in the output
+    OutputMatches {
+        task: u32,
+        pattern: String,
+    },
+}
+
+impl RunCondition {
+    pub fn task(&self) -> u32 {
+        match self {
+            RunCondition::ExitCode { task, .. }
+            | RunCondition::OutputEmpty { task }
+            | RunCondition::OutputNotEmpty { task }
+            | RunCondition::OutputMatches { task, .. } => *task,
+        }
+    }
+
+    /// Shift the referenced task number, for tasks appended to a plan
+    pub fn offset_task(&mut self, offset: u32) {
+        match self {
+            RunCondition::ExitCode { task, .. }
+            | RunCondition::OutputEmpty { task }
+            | RunCondition::OutputNotEmpty { task }
+            | RunCondition::OutputMatches { task, .. } => *task += offset,
+        }
+    }
+
+    /// Point the condition at another task
+    pub fn set_task(&mut self, new_task: u32) {
+        match self {
+            RunCondition::ExitCode { task, .. }
+            | RunCondition::OutputEmpty { task }
+            | RunCondition::OutputNotEmpty { task }
+            | RunCondition::OutputMatches { task, .. } => *task = new_task,
+        }
+    }
+
+    /// Check that the condition itself is well-formed
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            RunCondition::OutputMatches { pattern, .. } => regex::bytes::Regex::new(pattern)
+                .map(|_| ())
+                .map_err(|error| format!("invalid output_matches pattern: {error}")),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What happens to the run when a task fails. Tasks reading the output of a
+/// failed task are skipped unless the run stops.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Fail the whole run
+    #[default]
+    Stop,
+    /// Record the failure and keep running the tasks that do not depend on it
+    Continue,
+    /// Like `Continue`, and run this task, which is skipped otherwise
+    Fallback(u32),
+}
+
+/// Run a failing task again, waiting `backoff_base_ms` before the second
+/// attempt and doubling the wait up to `backoff_max_ms` after each further
+/// failure. Only failing exit codes are retried, not timeouts or limits.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first one
+    pub max_attempts: u32,
+    #[serde(default = "default_backoff_base_ms")]
+    pub backoff_base_ms: u64,
+    #[serde(default = "default_backoff_max_ms")]
+    pub backoff_max_ms: u64,
+    /// Exit codes worth another attempt; empty retries any failing exit code
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retryable_exit_codes: Vec<i32>,
+}
+
+fn default_backoff_base_ms() -> u64 {
+    1000
+}
+
+fn default_backoff_max_ms() -> u64 {
+    30_000
+}
+
+impl RetryPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_attempts == 0 {
+            return Err("retry.max_attempts must be at least 1".to_string());
+        }
+
+        if self.backoff_base_ms > self.backoff_max_ms {
+            return Err("retry.backoff_base_ms must not exceed retry.backoff_max_ms".to_string());
+        }
+
+        Ok(())
+    }
+
+    pub fn retries(&self, exit_code: i32) -> bool {
+        self.retryable_exit_codes.is_empty() || self.retryable_exit_codes.contains(&exit_code)
+    }
+
+    /// Wait before attempt `attempt + 1`, after `attempt` failures
+    pub fn backoff(&self, attempt: u32) -> std::time::Duration {
+        let factor = 1u64
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u64::MAX);
+        let millis = self.backoff_base_ms.saturating_mul(factor);
+
+        std::time::Duration::from_millis(millis.min(self.backoff_max_ms))
+    }
+}
+
+impl FailurePolicy {
+    pub fn is_stop(&self) -> bool {
+        *self == FailurePolicy::Stop
+    }
+}
+
+/// Input a plan is parameterized by. A parameter with neither a value nor
+/// a default resolves to nothing: a placeholder making up a whole arg drops
+/// that arg, and one inside a larger arg becomes an empty string.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PlanParameter {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub kind: ParameterType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<serde_json::Value>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub required: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// An `array` placeholder making up a whole arg expands to one arg per
+/// element; inside a larger arg the elements are joined with commas.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterType {
+    #[default]
+    String,
+    Integer,
+    Number,
+    Boolean,
+    Array,
+}
+
+/// A sample input together with what the plan must produce for it. Paths
+/// are relative to the directory containing the plan file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct PlanExample {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub input: String,
+    /// File holding the exact expected output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_output: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub assertions: Vec<OutputAssertion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputAssertion {
+    /// The whole output parses as a single JSON value
+    ValidJson,
+    LineCount {
+        equals: usize,
+    },
+    Contains {
+        text: String,
+    },
 }
 
 impl Default for WorkflowPlan {
     fn default() -> Self {
         Self {
+            schema_version: schema_version(),
             plan_id: None,
             plan_description: None,
             tasks: Vec::new(),
+            examples: Vec::new(),
+            parameters: Vec::new(),
+            input_schema: None,
         }
     }
 }
 
+impl PlanStep {
+    /// Upstream tasks of this step, from either `input_from_task` or
+    /// `inputs_from_tasks`. Errors when both are set.
+    pub fn input_tasks(&self) -> Result<Vec<u32>, String> {
+        match (self.input_from_task, self.inputs_from_tasks.is_empty()) {
+            (Some(_), false) => Err(format!(
+                "task {} sets both input_from_task and inputs_from_tasks",
+                self.task_number
+            )),
+            (Some(task), true) => Ok(vec![task]),
+            (None, _) => Ok(self.inputs_from_tasks.clone()),
+        }
+    }
+}
+
+/// A task with the default timeout and policies, for tests
+#[cfg(test)]
+pub(crate) fn step(task_number: u32, command: &str, args: &[&str], input: Option<u32>) -> PlanStep {
+    PlanStep {
+        task_number,
+        command: command.to_string(),
+        args: args.iter().map(|arg| arg.to_string()).collect(),
+        timeout_secs: 300,
+        input_from_task: input,
+        inputs_from_tasks: Vec::new(),
+        run_if: None,
+        on_failure: FailurePolicy::Stop,
+        retry: None,
+    }
+}
+
 impl WorkflowPlan {
     pub fn from_str(value: &str) -> Result<Self, serde_json::Error> {
         let cleaned = strip_markdown_fence(value);
         parse_any_form(&cleaned)
     }
 
-    pub fn normalize_for_execution(mut self) -> Self {
-        // Handle special case: bare "uniq" needs "sort" first
-        if self.tasks.len() == 1 && self.tasks[0].command == "uniq" {
-            self.tasks = vec![
-                PlanStep {
-                    task_number: 1,
-                    command: "sort".to_string(),
-                    args: Vec::new(),
-                    timeout_secs: 300,
-                    input_from_task: None,
-                },
-                PlanStep {
-                    task_number: 2,
-                    command: "uniq".to_string(),
-                    args: Vec::new(),
-                    timeout_secs: 300,
-                    input_from_task: Some(1),
-                },
-            ];
-        }
+    /// Apply the deterministic rewrite rules and number the tasks from 1.
+    /// Returns the rules that fired.
+    pub fn normalize_for_execution(mut self) -> (Self, Vec<crate::plan_rewrite::Fired>) {
+        let fired = crate::plan_rewrite::rewrite(&mut self, crate::plan_rewrite::RULES);
 
         // Ensure contiguous task numbering (1-based)
         for (index, task) in self.tasks.iter_mut().enumerate() {
             task.task_number = (index + 1) as u32;
         }
 
-        self
+        (self, fired)
     }
 }
 
@@ -130,122 +368,21 @@ fn parse_any_form(text: &str) -> Result<WorkflowPlan, serde_json::Error> {
     serde_json::from_str::<WorkflowPlan>(text)
 }
 
+/// Parse `text` as a plan in any schema version, migrating it to the
+/// current one
 fn try_all_known_forms(text: &str) -> Option<WorkflowPlan> {
-    // Try parsing as canonical schema (new format)
-    if let Ok(mut plan) = serde_json::from_str::<WorkflowPlan>(text) {
-        // Ensure task numbering is correct
-        for (index, task) in plan.tasks.iter_mut().enumerate() {
-            if task.task_number == 0 {
-                task.task_number = (index + 1) as u32;
-            }
-        }
-        return Some(plan);
-    }
-
-    // Try legacy format with "plan" field
-    #[derive(Deserialize)]
-    struct LegacyWorkflowPlan {
-        plan: Vec<LegacyPlanStep>,
-    }
+    let document = serde_json::from_str(text).ok()?;
+    let (document, _) = crate::plan_migrate::migrate(document).ok()?;
+    let mut plan = serde_json::from_value::<WorkflowPlan>(document).ok()?;
 
-    #[derive(Deserialize)]
-    struct LegacyPlanStep {
-        cmd: String,
-        #[serde(default)]
-        args: Vec<String>,
-        #[serde(default)]
-        input_from_step: Option<u32>,
-        #[serde(default)]
-        timeout_secs: Option<u32>,
-    }
-
-    if let Ok(legacy) = serde_json::from_str::<LegacyWorkflowPlan>(text) {
-        return Some(WorkflowPlan {
-            plan_id: None,
-            plan_description: None,
-            tasks: legacy
-                .plan
-                .into_iter()
-                .enumerate()
-                .map(|(index, step)| PlanStep {
-                    task_number: (index + 1) as u32,
-                    command: step.cmd,
-                    args: step.args,
-                    timeout_secs: step.timeout_secs.unwrap_or(300),
-                    input_from_task: step.input_from_step,
-                })
-                .collect(),
-        });
-    }
-
-    // Try simple format {"plan": ["cmd1", "cmd2"]}
-    if let Ok(simple) = serde_json::from_str::<SimpleWorkflowPlan>(text) {
-        return Some(WorkflowPlan {
-            plan_id: None,
-            plan_description: None,
-            tasks: simple
-                .plan
-                .into_iter()
-                .enumerate()
-                .map(|(index, cmd)| PlanStep {
-                    task_number: (index + 1) as u32,
-                    command: cmd,
-                    args: Vec::new(),
-                    timeout_secs: 300,
-                    input_from_task: None,
-                })
-                .collect(),
-        });
-    }
-
-    // Try array of tasks
-    if let Ok(steps) = serde_json::from_str::<Vec<PlanStep>>(text) {
-        return Some(WorkflowPlan {
-            plan_id: None,
-            plan_description: None,
-            tasks: steps,
-        });
-    }
-
-    // Try array of legacy steps
-    if let Ok(legacy_steps) = serde_json::from_str::<Vec<LegacyPlanStep>>(text) {
-        return Some(WorkflowPlan {
-            plan_id: None,
-            plan_description: None,
-            tasks: legacy_steps
-                .into_iter()
-                .enumerate()
-                .map(|(index, step)| PlanStep {
-                    task_number: (index + 1) as u32,
-                    command: step.cmd,
-                    args: step.args,
-                    timeout_secs: step.timeout_secs.unwrap_or(300),
-                    input_from_task: step.input_from_step,
-                })
-                .collect(),
-        });
-    }
-
-    // Try simple array of command strings
-    if let Ok(cmds) = serde_json::from_str::<Vec<String>>(text) {
-        return Some(WorkflowPlan {
-            plan_id: None,
-            plan_description: None,
-            tasks: cmds
-                .into_iter()
-                .enumerate()
-                .map(|(index, cmd)| PlanStep {
-                    task_number: (index + 1) as u32,
-                    command: cmd,
-                    args: Vec::new(),
-                    timeout_secs: 300,
-                    input_from_task: None,
-                })
-                .collect(),
-        });
+    // Ensure task numbering is correct
+    for (index, task) in plan.tasks.iter_mut().enumerate() {
+        if task.task_number == 0 {
+            task.task_number = (index + 1) as u32;
+        }
     }
 
-    None
+    Some(plan)
 }
 
 fn extract_first_json_value(text: &str) -> Option<&str> {
@@ -394,4 +531,21 @@ mod tests {
 
         assert_eq!(valid, repaired);
     }
+
+    #[test]
+    fn retry_backoff_doubles_up_to_the_maximum() {
+        let retry = RetryPolicy {
+            max_attempts: 5,
+            backoff_base_ms: 100,
+            backoff_max_ms: 250,
+            retryable_exit_codes: vec![75],
+        };
+
+        assert_eq!(retry.backoff(1).as_millis(), 100);
+        assert_eq!(retry.backoff(2).as_millis(), 200);
+        assert_eq!(retry.backoff(3).as_millis(), 250);
+        assert_eq!(retry.backoff(100).as_millis(), 250);
+        assert!(retry.retries(75));
+        assert!(!retry.retries(1));
+    }
 }
@@ -2,6 +2,7 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 use crate::plan::WorkflowPlan;
+use crate::plan_format::{self, PlanFormat};
 
 pub struct PlanStorage {
     path: PathBuf,
@@ -19,10 +20,18 @@ impl PlanStorage {
             return Self::new(PathBuf::from(path));
         }
 
-        let mut path = std::env::temp_dir();
-        path.push("agx-plan.json");
+        Self::new(Self::temporary_path())
+    }
+
+    /// Where the buffer lives unless `AGX_PLAN_PATH` says otherwise
+    fn temporary_path() -> PathBuf {
+        std::env::temp_dir().join("agx-plan.json")
+    }
 
-        Self::new(path)
+    /// Whether this is the default buffer in the temp directory, rather
+    /// than a plan file the user keeps
+    pub fn is_temporary(&self) -> bool {
+        self.path == Self::temporary_path()
     }
 
     pub fn new(path: PathBuf) -> Self {
@@ -46,7 +55,8 @@ impl PlanStorage {
                     return Ok(WorkflowPlan::default());
                 }
 
-                serde_json::from_str(&contents).map_err(|error| {
+                let format = PlanFormat::from_path(&self.path);
+                plan_format::parse_plan(&contents, format).map_err(|error| {
                     format!(
                         "failed to parse plan buffer {}: {error}",
                         self.display_path()
@@ -75,10 +85,10 @@ impl PlanStorage {
             }
         }
 
-        let json = serde_json::to_string_pretty(plan)
+        let text = plan_format::write_plan(plan, PlanFormat::from_path(&self.path))
             .map_err(|error| format!("failed to serialize plan buffer: {error}"))?;
 
-        fs::write(&self.path, json).map_err(|error| {
+        fs::write(&self.path, text).map_err(|error| {
             format!(
                 "failed to write plan buffer {}: {error}",
                 self.display_path()
@@ -133,7 +143,7 @@ impl PlanStorage {
 mod tests {
     use super::*;
 
-    use crate::plan::{PlanStep, WorkflowPlan};
+    use crate::plan::{FailurePolicy, PlanStep, WorkflowPlan, SCHEMA_VERSION};
 
     fn temp_path(name: &str) -> PathBuf {
         let mut path = std::env::temp_dir();
@@ -174,7 +184,15 @@ mod tests {
                 args: vec!["-r".to_string()],
                 timeout_secs: 300,
                 input_from_task: None,
+                inputs_from_tasks: Vec::new(),
+                run_if: None,
+                on_failure: FailurePolicy::Stop,
+                retry: None,
             }],
+            examples: Vec::new(),
+            parameters: Vec::new(),
+            input_schema: None,
+            schema_version: SCHEMA_VERSION.to_string(),
         };
 
         storage.save(&plan).expect("save should succeed");
@@ -187,6 +205,13 @@ mod tests {
         fs::remove_file(path).unwrap();
     }
 
+    #[test]
+    fn only_the_default_buffer_is_temporary() {
+        let default = PlanStorage::new(std::env::temp_dir().join("agx-plan.json"));
+        assert!(default.is_temporary());
+        assert!(!PlanStorage::new(temp_path("kept")).is_temporary());
+    }
+
     #[test]
     fn save_submission_metadata_writes_file() {
         let path = temp_path("meta");
@@ -0,0 +1,206 @@
+//! Preflight for `PLAN check`: everything that can be verified about a plan
+//! without running it. Tools are resolved against the registry and then on
+//! `$PATH`, `input_from_task` references are checked, and the argv of every
+//! task is reported as `PLAN run` would spawn it. Placeholders are checked
+//! against the declared parameters but left unresolved.
+
+use serde::Serialize;
+
+use crate::plan::WorkflowPlan;
+use crate::plan_graph::PlanGraph;
+use crate::plan_params;
+use crate::registry::ToolRegistry;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanCheck {
+    pub tasks: Vec<TaskCheck>,
+    /// Problems that concern the plan as a whole, such as broken references
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskCheck {
+    pub task_number: u32,
+    pub command: String,
+    /// Resolved binary, when the tool is known and found on `$PATH`
+    pub binary_path: Option<String>,
+    pub argv: Vec<String>,
+    /// `"plan input"`, `"task N"` or `"task N + task M"`, when the
+    /// references are valid
+    pub input: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+impl PlanCheck {
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty() && self.tasks.iter().all(|task| task.errors.is_empty())
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.errors.len()
+            + self
+                .tasks
+                .iter()
+                .map(|task| task.errors.len())
+                .sum::<usize>()
+    }
+}
+
+impl TaskCheck {
+    /// The argv as a shell command line
+    pub fn command_line(&self) -> String {
+        self.argv
+            .iter()
+            .map(|arg| quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+pub fn check_plan(plan: &WorkflowPlan, registry: &ToolRegistry) -> PlanCheck {
+    let mut errors = Vec::new();
+
+    let graph = match PlanGraph::build(plan) {
+        Ok(graph) => Some(graph),
+        Err(error) => {
+            errors.push(error);
+            None
+        }
+    };
+
+    if let Err(error) = plan_params::validate_plan(plan) {
+        errors.push(error);
+    }
+
+    if let Some(Err(error)) = plan.input_schema.as_ref().map(plan_params::compile_schema) {
+        errors.push(format!("invalid input_schema: {error}"));
+    }
+
+    let tasks = plan
+        .tasks
+        .iter()
+        .enumerate()
+        .map(|(index, task)| {
+            let mut task_errors = Vec::new();
+
+            let binary_path = match registry.find_by_id(&task.command) {
+                Some(tool) => {
+                    let binary = tool.resolve_binary();
+                    if binary.is_none() {
+                        task_errors.push(format!("`{}` was not found on $PATH", tool.command));
+                    }
+                    binary
+                }
+                None => {
+                    task_errors.push(format!("unknown tool in plan: {}", task.command));
+                    None
+                }
+            };
+
+            if let Some(Err(error)) = task.retry.as_ref().map(|retry| retry.validate()) {
+                task_errors.push(error);
+            }
+
+            let program = match &binary_path {
+                Some(path) => path.display().to_string(),
+                None => task.command.clone(),
+            };
+
+            let input = graph.as_ref().map(|graph| match graph.sources(index) {
+                [] => "plan input".to_string(),
+                sources => sources
+                    .iter()
+                    .map(|&source| format!("task {}", plan.tasks[source].task_number))
+                    .collect::<Vec<_>>()
+                    .join(" + "),
+            });
+
+            TaskCheck {
+                task_number: task.task_number,
+                command: task.command.clone(),
+                binary_path: binary_path.map(|path| path.display().to_string()),
+                argv: std::iter::once(program)
+                    .chain(task.args.iter().cloned())
+                    .collect(),
+                input,
+                errors: task_errors,
+            }
+        })
+        .collect();
+
+    PlanCheck { tasks, errors }
+}
+
+/// Single-quote `arg` unless it only contains characters the shell leaves alone
+pub(crate) fn quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{step, PlanStep};
+
+    fn plan(tasks: Vec<PlanStep>) -> WorkflowPlan {
+        WorkflowPlan {
+            tasks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resolves_argv_and_inputs() {
+        let check = check_plan(
+            &plan(vec![
+                step(1, "grep", &["-c", "an error"], None),
+                step(2, "sort", &[], Some(1)),
+            ]),
+            &ToolRegistry::new(),
+        );
+
+        assert!(check.is_ok(), "{check:?}");
+        let grep = &check.tasks[0];
+        assert!(grep.argv[0].ends_with("/grep"));
+        assert_eq!(grep.argv[1..], ["-c", "an error"]);
+        assert!(grep.command_line().ends_with("/grep -c 'an error'"));
+        assert_eq!(grep.input.as_deref(), Some("plan input"));
+        assert_eq!(check.tasks[1].input.as_deref(), Some("task 1"));
+    }
+
+    #[test]
+    fn reports_unknown_tools_and_broken_references() {
+        let check = check_plan(
+            &plan(vec![
+                step(1, "rm", &["-rf", "/"], None),
+                step(2, "sort", &[], Some(7)),
+            ]),
+            &ToolRegistry::new(),
+        );
+
+        assert!(!check.is_ok());
+        assert_eq!(check.error_count(), 2);
+        assert_eq!(check.tasks[0].errors, ["unknown tool in plan: rm"]);
+        assert_eq!(check.tasks[0].argv[0], "rm");
+        assert!(check.errors[0].contains("unknown task 7"));
+        assert!(check.tasks[1].input.is_none());
+    }
+
+    #[test]
+    fn quotes_only_when_needed() {
+        assert_eq!(quote("-d,"), "-d,");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote("a|b"), "'a|b'");
+    }
+}
@@ -0,0 +1,278 @@
+//! `PLAN graph`: draw the data flow of a plan as a Graphviz (DOT) digraph
+//! or a Mermaid flowchart, for design reviews and docs.
+//!
+//! Each task is a node labeled with its number, command and arguments.
+//! Solid edges carry output from one task to the tasks reading it, as
+//! `PlanGraph` resolves them (including the implicit chain of a linear
+//! plan); tasks reading the plan input hang off an `input` node, and outputs
+//! no task reads lead to an `output` node. Dashed edges show which task a
+//! `run_if` looks at and which task a fallback stands in for.
+
+use std::fmt::Write as _;
+
+use crate::plan::{RunCondition, WorkflowPlan};
+use crate::plan_check::quote;
+use crate::plan_graph::PlanGraph;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagramFormat {
+    /// Graphviz DOT
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+}
+
+impl DiagramFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "dot" | "graphviz" => Some(DiagramFormat::Dot),
+            "mermaid" => Some(DiagramFormat::Mermaid),
+            _ => None,
+        }
+    }
+}
+
+struct Edge {
+    from: String,
+    to: String,
+    /// Set on the dashed control edges
+    label: Option<String>,
+}
+
+pub fn render(plan: &WorkflowPlan, format: DiagramFormat) -> Result<String, String> {
+    if plan.tasks.is_empty() {
+        return Err("plan is empty; there is nothing to draw".to_string());
+    }
+
+    let graph = PlanGraph::build(plan)?;
+    let node = |index: usize| format!("t{}", plan.tasks[index].task_number);
+
+    let labels: Vec<String> = plan
+        .tasks
+        .iter()
+        .map(|task| {
+            let words: Vec<String> = std::iter::once(task.command.clone())
+                .chain(task.args.iter().map(|arg| quote(arg)))
+                .collect();
+            format!("{}: {}", task.task_number, words.join(" "))
+        })
+        .collect();
+
+    let mut edges = Vec::new();
+    for (index, task) in plan.tasks.iter().enumerate() {
+        if graph.reads_plan_input(index) {
+            edges.push(Edge {
+                from: "input".to_string(),
+                to: node(index),
+                label: None,
+            });
+        }
+        for &source in graph.sources(index) {
+            edges.push(Edge {
+                from: node(source),
+                to: node(index),
+                label: None,
+            });
+        }
+        if let (Some(condition), Some(run_if)) = (graph.condition(index), &task.run_if) {
+            edges.push(Edge {
+                from: node(condition),
+                to: node(index),
+                label: Some(format!("if {}", describe(run_if))),
+            });
+        }
+        if let Some(replaced) = graph.fallback_for(index) {
+            edges.push(Edge {
+                from: node(replaced),
+                to: node(index),
+                label: Some("on failure".to_string()),
+            });
+        }
+    }
+    for index in graph.sinks() {
+        edges.push(Edge {
+            from: node(index),
+            to: "output".to_string(),
+            label: None,
+        });
+    }
+
+    let uses_input = (0..plan.tasks.len()).any(|index| graph.reads_plan_input(index));
+    let nodes: Vec<(String, &str)> = (0..plan.tasks.len())
+        .map(|index| (node(index), labels[index].as_str()))
+        .collect();
+
+    Ok(match format {
+        DiagramFormat::Dot => dot(&nodes, &edges, uses_input),
+        DiagramFormat::Mermaid => mermaid(&nodes, &edges, uses_input),
+    })
+}
+
+/// Short wording of a condition for its edge label
+fn describe(condition: &RunCondition) -> String {
+    match condition {
+        RunCondition::ExitCode { equals, .. } => format!("exit code = {equals}"),
+        RunCondition::OutputEmpty { .. } => "output empty".to_string(),
+        RunCondition::OutputNotEmpty { .. } => "output not empty".to_string(),
+        RunCondition::OutputMatches { pattern, .. } => format!("output matches /{pattern}/"),
+    }
+}
+
+fn dot(nodes: &[(String, &str)], edges: &[Edge], uses_input: bool) -> String {
+    // Inside a DOT string only `"` and `\` need escaping
+    let escape = |text: &str| text.replace('\\', r"\\").replace('"', "\\\"");
+
+    let mut out = String::from("digraph plan {\n    rankdir=LR;\n    node [shape=box];\n");
+    if uses_input {
+        out.push_str("    input [shape=ellipse];\n");
+    }
+    for (id, label) in nodes {
+        let _ = writeln!(out, "    {id} [label=\"{}\"];", escape(label));
+    }
+    out.push_str("    output [shape=ellipse];\n");
+
+    for edge in edges {
+        match &edge.label {
+            None => {
+                let _ = writeln!(out, "    {} -> {};", edge.from, edge.to);
+            }
+            Some(label) => {
+                let _ = writeln!(
+                    out,
+                    "    {} -> {} [style=dashed, label=\"{}\"];",
+                    edge.from,
+                    edge.to,
+                    escape(label)
+                );
+            }
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn mermaid(nodes: &[(String, &str)], edges: &[Edge], uses_input: bool) -> String {
+    // Mermaid labels are HTML; entity codes keep quotes, tags and `|` literal
+    let escape = |text: &str| {
+        text.chars()
+            .map(|c| match c {
+                '#' => "#35;".to_string(),
+                '"' => "#quot;".to_string(),
+                '<' => "#lt;".to_string(),
+                '>' => "#gt;".to_string(),
+                '|' => "#124;".to_string(),
+                c => c.to_string(),
+            })
+            .collect::<String>()
+    };
+
+    let mut out = String::from("flowchart LR\n");
+    if uses_input {
+        out.push_str("    input([input])\n");
+    }
+    for (id, label) in nodes {
+        let _ = writeln!(out, "    {id}[\"{}\"]", escape(label));
+    }
+    out.push_str("    output([output])\n");
+
+    for edge in edges {
+        match &edge.label {
+            None => {
+                let _ = writeln!(out, "    {} --> {}", edge.from, edge.to);
+            }
+            Some(label) => {
+                let _ = writeln!(
+                    out,
+                    "    {} -.->|\"{}\"| {}",
+                    edge.from,
+                    escape(label),
+                    edge.to
+                );
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{step, FailurePolicy, PlanStep};
+
+    fn plan(tasks: Vec<PlanStep>) -> WorkflowPlan {
+        WorkflowPlan {
+            tasks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn draws_a_linear_plan() {
+        let plan = plan(vec![
+            step(1, "grep", &["say \"hi\""], None),
+            step(2, "sort", &["-r"], None),
+        ]);
+
+        assert_eq!(
+            render(&plan, DiagramFormat::Dot).unwrap(),
+            "digraph plan {\n    rankdir=LR;\n    node [shape=box];\n    input [shape=ellipse];\n    t1 [label=\"1: grep 'say \\\"hi\\\"'\"];\n    t2 [label=\"2: sort -r\"];\n    output [shape=ellipse];\n    input -> t1;\n    t1 -> t2;\n    t2 -> output;\n}\n"
+        );
+        assert_eq!(
+            render(&plan, DiagramFormat::Mermaid).unwrap(),
+            "flowchart LR\n    input([input])\n    t1[\"1: grep 'say #quot;hi#quot;'\"]\n    t2[\"2: sort -r\"]\n    output([output])\n    input --> t1\n    t1 --> t2\n    t2 --> output\n"
+        );
+    }
+
+    #[test]
+    fn draws_fan_in_conditions_and_fallbacks() {
+        let mut tasks = vec![
+            step(1, "grep", &["-E", "ERROR|FATAL"], None),
+            step(2, "grep", &["WARN"], None),
+            step(3, "sort", &[], None),
+            step(4, "tr", &["a-z", "A-Z"], Some(2)),
+        ];
+        tasks[2].inputs_from_tasks = vec![1, 2];
+        tasks[3].run_if = Some(RunCondition::OutputNotEmpty { task: 1 });
+        tasks[0].on_failure = FailurePolicy::Fallback(2);
+
+        let mermaid = render(&plan(tasks), DiagramFormat::Mermaid).unwrap();
+        let edges: Vec<&str> = mermaid
+            .lines()
+            .filter(|line| line.contains("->"))
+            .map(str::trim)
+            .collect();
+
+        assert!(mermaid.contains("t1[\"1: grep -E 'ERROR#124;FATAL'\"]"));
+        assert_eq!(
+            edges,
+            [
+                "input --> t1",
+                "input --> t2",
+                "t1 -.->|\"on failure\"| t2",
+                "t1 --> t3",
+                "t2 --> t3",
+                "t2 --> t4",
+                "t1 -.->|\"if output not empty\"| t4",
+                "t3 --> output",
+                "t4 --> output",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_broken_plans() {
+        let tasks = vec![step(1, "sort", &[], Some(2)), step(2, "uniq", &[], Some(1))];
+        assert!(render(&plan(tasks), DiagramFormat::Dot).is_err());
+        assert!(render(&plan(Vec::new()), DiagramFormat::Mermaid).is_err());
+    }
+
+    #[test]
+    fn parses_format_names() {
+        assert_eq!(DiagramFormat::parse("DOT"), Some(DiagramFormat::Dot));
+        assert_eq!(
+            DiagramFormat::parse("mermaid"),
+            Some(DiagramFormat::Mermaid)
+        );
+        assert_eq!(DiagramFormat::parse("svg"), None);
+    }
+}
@@ -0,0 +1,584 @@
+//! Task-level diff between two plans, for `PLAN diff` and for showing what
+//! Delta changed in `PLAN validate`.
+//!
+//! Tasks are aligned by a longest common subsequence on command and args.
+//! Tasks left over with the same command and args elsewhere in the other plan
+//! are paired as moved, and then tasks with the same command as modified.
+//! Anything still unpaired was added or removed. Renumbering alone is not a
+//! change: references are compared through the alignment, so a task whose
+//! input is still the same task counts as unchanged even if that task's
+//! number moved.
+
+use std::fmt;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::plan::{FailurePolicy, PlanStep, WorkflowPlan};
+use crate::plan_graph::PlanGraph;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PlanDiff {
+    /// Tasks only in the second plan
+    pub added: Vec<TaskSummary>,
+    /// Tasks only in the first plan
+    pub removed: Vec<TaskSummary>,
+    pub modified: Vec<ModifiedTask>,
+    /// Tasks that kept their command and args but changed places relative to
+    /// the other tasks
+    pub moved: Vec<PairedTask>,
+    /// Tasks reading from different tasks than before
+    pub rewired: Vec<RewiredTask>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskSummary {
+    pub task_number: u32,
+    pub command: String,
+    pub args: Vec<String>,
+}
+
+/// A task of the first plan (`before`) and its counterpart in the second
+/// (`after`), by task number
+#[derive(Debug, Clone, Serialize)]
+pub struct PairedTask {
+    pub before: u32,
+    pub after: u32,
+    pub command: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModifiedTask {
+    #[serde(flatten)]
+    pub task: PairedTask,
+    pub changes: Vec<FieldChange>,
+}
+
+/// One changed field, such as `args[1]` or `timeout_secs`. A missing side
+/// means the field or arg did not exist there.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+/// Inputs are task numbers of the plan they belong to; empty is the plan
+/// input
+#[derive(Debug, Clone, Serialize)]
+pub struct RewiredTask {
+    #[serde(flatten)]
+    pub task: PairedTask,
+    pub inputs_before: Vec<u32>,
+    pub inputs_after: Vec<u32>,
+}
+
+impl PlanDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+            && self.moved.is_empty()
+            && self.rewired.is_empty()
+    }
+
+    /// Counts of each kind of change, e.g. `1 added, 2 modified`
+    pub fn summary(&self) -> String {
+        let counts = [
+            (self.added.len(), "added"),
+            (self.removed.len(), "removed"),
+            (self.modified.len(), "modified"),
+            (self.moved.len(), "moved"),
+            (self.rewired.len(), "rewired"),
+        ];
+
+        let parts: Vec<String> = counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, kind)| format!("{count} {kind}"))
+            .collect();
+
+        if parts.is_empty() {
+            "no changes".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+impl fmt::Display for PlanDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No differences");
+        }
+
+        for task in &self.removed {
+            writeln!(
+                f,
+                "- removed  task {}: {}",
+                task.task_number,
+                command_line(task)
+            )?;
+        }
+        for task in &self.added {
+            writeln!(
+                f,
+                "+ added    task {}: {}",
+                task.task_number,
+                command_line(task)
+            )?;
+        }
+        for modified in &self.modified {
+            writeln!(f, "~ modified {}", modified.task)?;
+            for change in &modified.changes {
+                writeln!(
+                    f,
+                    "    {}: {} → {}",
+                    change.field,
+                    show(&change.before),
+                    show(&change.after)
+                )?;
+            }
+        }
+        for task in &self.moved {
+            writeln!(f, "↕ moved    {task}")?;
+        }
+        for rewired in &self.rewired {
+            writeln!(
+                f,
+                "⇄ rewired  {}: reads {} → {}",
+                rewired.task,
+                show_inputs(&rewired.inputs_before),
+                show_inputs(&rewired.inputs_after)
+            )?;
+        }
+
+        writeln!(f, "\n{}", self.summary())
+    }
+}
+
+impl fmt::Display for PairedTask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.before == self.after {
+            write!(f, "task {} ({})", self.before, self.command)
+        } else {
+            write!(
+                f,
+                "task {} → {} ({})",
+                self.before, self.after, self.command
+            )
+        }
+    }
+}
+
+fn command_line(task: &TaskSummary) -> String {
+    std::iter::once(task.command.as_str())
+        .chain(task.args.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn show(value: &Option<Value>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "(none)".to_string(),
+    }
+}
+
+fn show_inputs(inputs: &[u32]) -> String {
+    if inputs.is_empty() {
+        return "plan input".to_string();
+    }
+
+    inputs
+        .iter()
+        .map(|task| format!("task {task}"))
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+pub fn diff(before: &WorkflowPlan, after: &WorkflowPlan) -> PlanDiff {
+    let pairs = align(&before.tasks, &after.tasks);
+
+    let mut matched_before = vec![None; before.tasks.len()];
+    let mut matched_after = vec![false; after.tasks.len()];
+    for pair in &pairs {
+        matched_before[pair.before] = Some(pair.after);
+        matched_after[pair.after] = true;
+    }
+
+    // Task number in `before` → task number of its counterpart in `after`
+    let translate = |task_number: u32| {
+        before
+            .tasks
+            .iter()
+            .position(|task| task.task_number == task_number)
+            .and_then(|index| matched_before[index])
+            .map(|index| after.tasks[index].task_number)
+    };
+
+    let sources_before = sources(before);
+    let sources_after = sources(after);
+
+    let mut result = PlanDiff {
+        removed: unmatched(&before.tasks, |index| matched_before[index].is_some()),
+        added: unmatched(&after.tasks, |index| matched_after[index]),
+        ..PlanDiff::default()
+    };
+
+    for pair in &pairs {
+        let (old, new) = (&before.tasks[pair.before], &after.tasks[pair.after]);
+        let task = PairedTask {
+            before: old.task_number,
+            after: new.task_number,
+            command: new.command.clone(),
+        };
+
+        let changes = field_changes(old, new, &translate);
+        if !changes.is_empty() {
+            result.modified.push(ModifiedTask {
+                task: task.clone(),
+                changes,
+            });
+        }
+
+        if pair.moved {
+            result.moved.push(task.clone());
+        }
+
+        let inputs_before: Vec<u32> = sources_before[pair.before]
+            .iter()
+            .map(|&source| before.tasks[source].task_number)
+            .collect();
+        let inputs_after: Vec<u32> = sources_after[pair.after]
+            .iter()
+            .map(|&source| after.tasks[source].task_number)
+            .collect();
+        let translated: Vec<Option<u32>> =
+            inputs_before.iter().map(|&task| translate(task)).collect();
+
+        if translated
+            != inputs_after
+                .iter()
+                .map(|&task| Some(task))
+                .collect::<Vec<_>>()
+        {
+            result.rewired.push(RewiredTask {
+                task,
+                inputs_before,
+                inputs_after,
+            });
+        }
+    }
+
+    result
+}
+
+type Matches = fn(&PlanStep, &PlanStep) -> bool;
+
+struct Pair {
+    before: usize,
+    after: usize,
+    moved: bool,
+}
+
+fn align(before: &[PlanStep], after: &[PlanStep]) -> Vec<Pair> {
+    // lengths[i][j]: LCS of before[i..] and after[j..]
+    let mut lengths = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            lengths[i][j] = if same_task(&before[i], &after[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() && j < after.len() {
+        if same_task(&before[i], &after[j]) {
+            pairs.push(Pair {
+                before: i,
+                after: j,
+                moved: false,
+            });
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    let mut paired_before = vec![false; before.len()];
+    let mut paired_after = vec![false; after.len()];
+    for pair in &pairs {
+        paired_before[pair.before] = true;
+        paired_after[pair.after] = true;
+    }
+
+    // Out of order but otherwise identical, then same command only
+    let passes: [(Matches, bool); 2] = [(same_task, true), (same_command, false)];
+    for (matches, moved) in passes {
+        for (i, old) in before.iter().enumerate() {
+            if paired_before[i] {
+                continue;
+            }
+
+            let found = (0..after.len()).find(|&j| !paired_after[j] && matches(old, &after[j]));
+            if let Some(j) = found {
+                paired_before[i] = true;
+                paired_after[j] = true;
+                pairs.push(Pair {
+                    before: i,
+                    after: j,
+                    moved,
+                });
+            }
+        }
+    }
+
+    pairs.sort_by_key(|pair| pair.after);
+    pairs
+}
+
+fn same_task(old: &PlanStep, new: &PlanStep) -> bool {
+    old.command == new.command && old.args == new.args
+}
+
+fn same_command(old: &PlanStep, new: &PlanStep) -> bool {
+    old.command == new.command
+}
+
+fn unmatched(tasks: &[PlanStep], matched: impl Fn(usize) -> bool) -> Vec<TaskSummary> {
+    tasks
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !matched(*index))
+        .map(|(_, task)| TaskSummary {
+            task_number: task.task_number,
+            command: task.command.clone(),
+            args: task.args.clone(),
+        })
+        .collect()
+}
+
+/// Positions each task reads from, as the executor sees them; the declared
+/// references when the plan does not form a valid graph
+fn sources(plan: &WorkflowPlan) -> Vec<Vec<usize>> {
+    if let Ok(graph) = PlanGraph::build(plan) {
+        return (0..plan.tasks.len())
+            .map(|index| graph.sources(index).to_vec())
+            .collect();
+    }
+
+    plan.tasks
+        .iter()
+        .map(|task| {
+            task.input_tasks()
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|&reference| {
+                    plan.tasks
+                        .iter()
+                        .position(|task| task.task_number == reference)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn field_changes(
+    old: &PlanStep,
+    new: &PlanStep,
+    translate: &dyn Fn(u32) -> Option<u32>,
+) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    let mut compare = |field: String, before: Option<Value>, after: Option<Value>| {
+        if before != after {
+            changes.push(FieldChange {
+                field,
+                before,
+                after,
+            });
+        }
+    };
+
+    for index in 0..old.args.len().max(new.args.len()) {
+        compare(
+            format!("args[{index}]"),
+            old.args.get(index).map(|arg| Value::from(arg.as_str())),
+            new.args.get(index).map(|arg| Value::from(arg.as_str())),
+        );
+    }
+
+    compare(
+        "timeout_secs".to_string(),
+        Some(Value::from(old.timeout_secs)),
+        Some(Value::from(new.timeout_secs)),
+    );
+
+    // References are compared in the numbering of the second plan
+    let run_if = old.run_if.as_ref().map(|condition| {
+        let mut value = to_value(condition);
+        if let Some(task) = translate(condition.task()) {
+            value["task"] = Value::from(task);
+        }
+        value
+    });
+    compare(
+        "run_if".to_string(),
+        run_if,
+        new.run_if.as_ref().map(to_value),
+    );
+
+    let on_failure = match old.on_failure {
+        FailurePolicy::Fallback(task) => FailurePolicy::Fallback(translate(task).unwrap_or(task)),
+        policy => policy,
+    };
+    compare(
+        "on_failure".to_string(),
+        Some(to_value(&on_failure)),
+        Some(to_value(&new.on_failure)),
+    );
+
+    compare(
+        "retry".to_string(),
+        old.retry.as_ref().map(to_value),
+        new.retry.as_ref().map(to_value),
+    );
+
+    changes
+}
+
+fn to_value<T: Serialize>(value: &T) -> Value {
+    serde_json::to_value(value).unwrap_or(Value::Null)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{step, RunCondition};
+
+    fn plan(tasks: Vec<PlanStep>) -> WorkflowPlan {
+        WorkflowPlan {
+            tasks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn identical_plans_have_no_differences() {
+        let a = plan(vec![
+            step(1, "sort", &[], None),
+            step(2, "uniq", &["-c"], None),
+        ]);
+        let diff = diff(&a, &a.clone());
+
+        assert!(diff.is_empty());
+        assert_eq!(diff.summary(), "no changes");
+    }
+
+    #[test]
+    fn insertion_renumbers_without_changing_anything_else() {
+        let a = plan(vec![
+            step(1, "grep", &["error"], None),
+            step(2, "sort", &[], Some(1)),
+        ]);
+        let b = plan(vec![
+            step(1, "grep", &["error"], None),
+            step(2, "cut", &["-f1"], Some(1)),
+            step(3, "sort", &[], Some(2)),
+        ]);
+
+        let diff = diff(&a, &b);
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].command, "cut");
+        assert!(diff.removed.is_empty() && diff.modified.is_empty() && diff.moved.is_empty());
+
+        // sort now reads cut instead of grep
+        assert_eq!(diff.rewired.len(), 1);
+        assert_eq!(diff.rewired[0].task.before, 2);
+        assert_eq!(diff.rewired[0].task.after, 3);
+        assert_eq!(diff.rewired[0].inputs_before, [1]);
+        assert_eq!(diff.rewired[0].inputs_after, [2]);
+    }
+
+    #[test]
+    fn reports_arg_changes_moves_and_removals() {
+        let a = plan(vec![
+            step(1, "sort", &["-n"], None),
+            step(2, "uniq", &["-c"], None),
+            step(3, "head", &["-n", "5"], None),
+            step(4, "wc", &["-l"], None),
+        ]);
+        let mut b = plan(vec![
+            step(1, "uniq", &["-c"], None),
+            step(2, "sort", &["-rn"], None),
+            step(3, "head", &["-n", "5"], None),
+        ]);
+        b.tasks[2].timeout_secs = 10;
+
+        let diff = diff(&a, &b);
+
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].command, "wc");
+
+        assert_eq!(diff.modified.len(), 2);
+        let sort = &diff.modified[0];
+        assert_eq!((sort.task.before, sort.task.after), (1, 2));
+        assert_eq!(sort.changes[0].field, "args[0]");
+        assert_eq!(sort.changes[0].before, Some(Value::from("-n")));
+        assert_eq!(sort.changes[0].after, Some(Value::from("-rn")));
+        assert_eq!(diff.modified[1].changes[0].field, "timeout_secs");
+
+        assert!(diff.moved.is_empty());
+        assert_eq!(diff.summary(), "1 removed, 2 modified, 3 rewired");
+        assert!(diff.to_string().contains("args[0]: \"-n\" → \"-rn\""));
+    }
+
+    #[test]
+    fn out_of_order_identical_tasks_are_moved() {
+        let a = plan(vec![
+            step(1, "grep", &["a"], None),
+            step(2, "grep", &["b"], None),
+            step(3, "sort", &[], None),
+        ]);
+        let b = plan(vec![
+            step(1, "sort", &[], None),
+            step(2, "grep", &["a"], None),
+            step(3, "grep", &["b"], None),
+        ]);
+
+        let diff = diff(&a, &b);
+        assert_eq!(diff.moved.len(), 1);
+        assert_eq!(diff.moved[0].command, "sort");
+        assert_eq!((diff.moved[0].before, diff.moved[0].after), (3, 1));
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+    }
+
+    #[test]
+    fn references_are_compared_through_the_alignment() {
+        let mut a = plan(vec![
+            step(1, "grep", &["x"], None),
+            step(2, "sort", &[], None),
+        ]);
+        a.tasks[1].run_if = Some(RunCondition::OutputNotEmpty { task: 1 });
+        a.tasks[1].input_from_task = Some(1);
+
+        let mut b = plan(vec![
+            step(1, "cat", &[], None),
+            step(2, "grep", &["x"], None),
+            step(3, "sort", &[], Some(2)),
+        ]);
+        b.tasks[2].run_if = Some(RunCondition::OutputNotEmpty { task: 2 });
+
+        let diff = diff(&a, &b);
+        assert_eq!(diff.added.len(), 1);
+        assert!(diff.modified.is_empty(), "{:?}", diff.modified);
+        assert!(diff.rewired.is_empty(), "{:?}", diff.rewired);
+    }
+}
@@ -0,0 +1,286 @@
+//! Regression checks for plans: run each `PlanExample` through the
+//! `Executor` and compare what the plan produces with what the example
+//! expects. Parameters are filled in once for all examples, as `PLAN run`
+//! fills them in.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::executor::{ExecutionInput, Executor};
+use crate::plan::{OutputAssertion, PlanExample, WorkflowPlan};
+use crate::plan_params;
+use crate::registry::ToolRegistry;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExampleResult {
+    pub name: String,
+    pub passed: bool,
+    pub failures: Vec<String>,
+}
+
+/// Run every example of `plan` with its parameters taken from `params`, or
+/// their defaults, resolving relative paths against `base_dir`. Errors when
+/// the parameters cannot be filled in.
+pub fn run_examples(
+    plan: &WorkflowPlan,
+    params: Option<&serde_json::Value>,
+    base_dir: &Path,
+    registry: &ToolRegistry,
+) -> Result<Vec<ExampleResult>, String> {
+    let resolved = plan_params::resolve_plan(plan, params)?;
+
+    Ok(resolved
+        .examples
+        .iter()
+        .enumerate()
+        .map(|(index, example)| {
+            let name = example
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("example {}", index + 1));
+            let failures = check_example(&resolved, example, base_dir, registry);
+
+            ExampleResult {
+                name,
+                passed: failures.is_empty(),
+                failures,
+            }
+        })
+        .collect())
+}
+
+fn check_example(
+    plan: &WorkflowPlan,
+    example: &PlanExample,
+    base_dir: &Path,
+    registry: &ToolRegistry,
+) -> Vec<String> {
+    if example.expected_output.is_none() && example.assertions.is_empty() {
+        return vec!["nothing to check: add `expected_output` or `assertions`".to_string()];
+    }
+
+    let input = resolve(base_dir, &example.input);
+    if !input.is_file() {
+        return vec![format!("input file {} not found", input.display())];
+    }
+
+    let mut output = Vec::new();
+    if let Err(error) =
+        Executor::new().execute_to(plan, &ExecutionInput::File(input), registry, &mut output)
+    {
+        return vec![format!("run failed: {error}")];
+    }
+
+    let mut failures = Vec::new();
+
+    if let Some(expected) = &example.expected_output {
+        let path = resolve(base_dir, expected);
+
+        match std::fs::read(&path) {
+            Ok(expected) => {
+                if let Some(difference) = describe_difference(&expected, &output) {
+                    failures.push(format!(
+                        "output differs from {}: {difference}",
+                        path.display()
+                    ));
+                }
+            }
+            Err(error) => failures.push(format!(
+                "failed to read expected output {}: {error}",
+                path.display()
+            )),
+        }
+    }
+
+    for assertion in &example.assertions {
+        if let Err(failure) = check_assertion(assertion, &output) {
+            failures.push(failure);
+        }
+    }
+
+    failures
+}
+
+fn resolve(base_dir: &Path, path: &str) -> PathBuf {
+    let path = Path::new(path);
+
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        base_dir.join(path)
+    }
+}
+
+/// Point at the first line where the output stops matching
+fn describe_difference(expected: &[u8], actual: &[u8]) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+
+    let expected_lines: Vec<&[u8]> = expected.split(|&byte| byte == b'\n').collect();
+    let actual_lines: Vec<&[u8]> = actual.split(|&byte| byte == b'\n').collect();
+
+    let line = expected_lines
+        .iter()
+        .zip(&actual_lines)
+        .position(|(expected, actual)| expected != actual)
+        .unwrap_or(expected_lines.len().min(actual_lines.len()));
+
+    let show = |lines: &[&[u8]]| match lines.get(line) {
+        Some(text) => format!("`{}`", String::from_utf8_lossy(text)),
+        None => "end of output".to_string(),
+    };
+
+    Some(format!(
+        "line {}: expected {}, got {}",
+        line + 1,
+        show(&expected_lines),
+        show(&actual_lines)
+    ))
+}
+
+fn check_assertion(assertion: &OutputAssertion, output: &[u8]) -> Result<(), String> {
+    match assertion {
+        OutputAssertion::ValidJson => serde_json::from_slice::<serde_json::Value>(output)
+            .map(|_| ())
+            .map_err(|error| format!("output is not valid JSON: {error}")),
+        OutputAssertion::LineCount { equals } => {
+            let lines = count_lines(output);
+
+            if lines == *equals {
+                Ok(())
+            } else {
+                Err(format!("expected {equals} line(s), got {lines}"))
+            }
+        }
+        OutputAssertion::Contains { text } => {
+            if String::from_utf8_lossy(output).contains(text.as_str()) {
+                Ok(())
+            } else {
+                Err(format!("output does not contain `{text}`"))
+            }
+        }
+    }
+}
+
+/// Lines as `wc -l` would count them, plus an unterminated last line
+fn count_lines(output: &[u8]) -> usize {
+    let newlines = output.iter().filter(|&&byte| byte == b'\n').count();
+
+    match output.last() {
+        Some(b'\n') | None => newlines,
+        Some(_) => newlines + 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{step, ParameterType, PlanParameter};
+
+    fn plan_with(examples: Vec<PlanExample>) -> WorkflowPlan {
+        WorkflowPlan {
+            tasks: vec![step(1, "sort", &[], None), step(2, "uniq", &[], None)],
+            examples,
+            ..Default::default()
+        }
+    }
+
+    fn example(expected_output: Option<&str>, assertions: Vec<OutputAssertion>) -> PlanExample {
+        PlanExample {
+            name: None,
+            input: "input.txt".to_string(),
+            expected_output: expected_output.map(str::to_string),
+            assertions,
+        }
+    }
+
+    #[test]
+    fn golden_output_and_assertions_pass() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("input.txt"), "b\na\nb\n").unwrap();
+        std::fs::write(dir.path().join("expected.txt"), "a\nb\n").unwrap();
+
+        let plan = plan_with(vec![example(
+            Some("expected.txt"),
+            vec![
+                OutputAssertion::LineCount { equals: 2 },
+                OutputAssertion::Contains {
+                    text: "a".to_string(),
+                },
+            ],
+        )]);
+
+        let results = run_examples(&plan, None, dir.path(), &ToolRegistry::new()).unwrap();
+        assert!(results[0].passed, "{:?}", results[0].failures);
+        assert_eq!(results[0].name, "example 1");
+    }
+
+    #[test]
+    fn mismatches_point_at_the_first_differing_line() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("input.txt"), "b\na\nb\n").unwrap();
+        std::fs::write(dir.path().join("expected.txt"), "a\nc\n").unwrap();
+
+        let plan = plan_with(vec![example(
+            Some("expected.txt"),
+            vec![OutputAssertion::ValidJson],
+        )]);
+
+        let results = run_examples(&plan, None, dir.path(), &ToolRegistry::new()).unwrap();
+        assert!(!results[0].passed);
+        assert!(results[0].failures[0].contains("line 2: expected `c`, got `b`"));
+        assert!(results[0].failures[1].contains("not valid JSON"));
+    }
+
+    #[test]
+    fn example_without_checks_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan = plan_with(vec![example(None, Vec::new())]);
+
+        let results = run_examples(&plan, None, dir.path(), &ToolRegistry::new()).unwrap();
+        assert!(results[0].failures[0].contains("nothing to check"));
+    }
+
+    #[test]
+    fn examples_run_with_parameters_filled_in() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("input.txt"), "error a\nok\nerror b\n").unwrap();
+
+        let mut plan = plan_with(vec![example(
+            None,
+            vec![OutputAssertion::LineCount { equals: 2 }],
+        )]);
+        plan.tasks.truncate(1);
+        plan.tasks[0].command = "grep".to_string();
+        plan.tasks[0].args = vec!["{{input.pattern}}".to_string()];
+        plan.parameters = vec![PlanParameter {
+            name: "pattern".to_string(),
+            kind: ParameterType::String,
+            default: Some(serde_json::json!("error")),
+            required: false,
+            description: None,
+        }];
+
+        let results = run_examples(&plan, None, dir.path(), &ToolRegistry::new()).unwrap();
+        assert!(results[0].passed, "{:?}", results[0].failures);
+
+        let params = serde_json::json!({"pattern": "ok"});
+        let results = run_examples(&plan, Some(&params), dir.path(), &ToolRegistry::new()).unwrap();
+        assert_eq!(results[0].failures, ["expected 2 line(s), got 1"]);
+
+        plan.parameters[0].required = true;
+        assert_eq!(
+            run_examples(&plan, None, dir.path(), &ToolRegistry::new()).unwrap_err(),
+            "missing required parameter `pattern`"
+        );
+    }
+
+    #[test]
+    fn counts_unterminated_last_line() {
+        assert_eq!(count_lines(b""), 0);
+        assert_eq!(count_lines(b"a\nb\n"), 2);
+        assert_eq!(count_lines(b"a\nb"), 2);
+    }
+}
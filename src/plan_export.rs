@@ -0,0 +1,493 @@
+//! `PLAN export`: render a plan in a form that runs without AGX, AGQ or
+//! AGW, to hand to someone else or attach to a ticket, or as a JSON, YAML
+//! or TOML plan file to edit by hand.
+//!
+//! As a POSIX shell script, a plain chain of tasks becomes a single
+//! pipeline. Any other plan, with fan-in, fan-out, conditions, failure
+//! policies or retries, runs task by task in dependency order with each
+//! output kept in a temporary file, mirroring how `PLAN run` executes it.
+//! Every command is wrapped in `timeout` with the task's `timeout_secs`.
+
+use std::fmt::Write as _;
+
+use crate::plan::{FailurePolicy, PlanStep, RunCondition, WorkflowPlan};
+use crate::plan_check::quote;
+use crate::plan_format::{self, PlanFormat};
+use crate::plan_graph::PlanGraph;
+use crate::registry::ToolRegistry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// POSIX shell script
+    Sh,
+    /// The plan itself, in a plan file format
+    Plan(PlanFormat),
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "sh" => Some(ExportFormat::Sh),
+            _ => PlanFormat::parse(name).map(ExportFormat::Plan),
+        }
+    }
+}
+
+pub fn export(
+    plan: &WorkflowPlan,
+    format: ExportFormat,
+    registry: &ToolRegistry,
+) -> Result<String, String> {
+    match format {
+        ExportFormat::Sh => shell_script(plan, registry),
+        ExportFormat::Plan(format) => plan_format::write_plan(plan, format),
+    }
+}
+
+/// Helpers of scripts that run tasks one by one. `run` records whether a
+/// task succeeded (by the tool's accepted exit codes) and its exit status.
+const TASK_HELPERS: &str = r#"tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+
+# run TASK OK_CODES COMMAND...: run COMMAND and record how task TASK ended
+run() {
+    task=$1
+    ok_codes=$2
+    shift 2
+    status=0
+    "$@" || status=$?
+    eval "status_$task=$status"
+    for code in $ok_codes; do
+        if [ "$status" -eq "$code" ]; then
+            eval "ok_$task=1"
+            return 0
+        fi
+    done
+    eval "failed_$task=1"
+    echo "task $task failed with exit status $status" >&2
+    return 1
+}
+ok() { eval "[ -n \"\${ok_$1:-}\" ]"; }
+failed() { eval "[ -n \"\${failed_$1:-}\" ]"; }
+"#;
+
+fn shell_script(plan: &WorkflowPlan, registry: &ToolRegistry) -> Result<String, String> {
+    if plan.tasks.is_empty() {
+        return Err("plan is empty; there is nothing to export".to_string());
+    }
+
+    let graph = PlanGraph::build(plan)?;
+
+    let mut script = String::from("#!/bin/sh\n");
+    if let Some(description) = &plan.plan_description {
+        for line in description.lines() {
+            let _ = writeln!(script, "# {line}");
+        }
+    }
+    script.push_str(
+        "# Exported from an agx plan. Reads the plan input on STDIN and writes the\n\
+         # plan output to STDOUT; each task is stopped after its timeout.\n",
+    );
+    if !plan.parameters.is_empty() {
+        script.push_str(
+            "# The plan declares parameters: replace the {{input.*}} placeholders before\n\
+             # running it.\n",
+        );
+    }
+    script.push_str("set -eu\n\n");
+
+    if is_pipeline(plan, &graph) {
+        pipeline(&mut script, plan, registry);
+    } else {
+        task_by_task(&mut script, plan, &graph, registry);
+    }
+
+    Ok(script)
+}
+
+/// A chain where each task reads the one before it and nothing else can
+/// change what runs
+fn is_pipeline(plan: &WorkflowPlan, graph: &PlanGraph) -> bool {
+    plan.tasks.iter().enumerate().all(|(index, task)| {
+        let chained = match index {
+            0 => graph.sources(0).is_empty(),
+            _ => graph.sources(index) == [index - 1],
+        };
+
+        chained && task.run_if.is_none() && task.on_failure.is_stop() && task.retry.is_none()
+    })
+}
+
+/// Like any `sh` pipeline, only the exit status of the last task counts
+fn pipeline(script: &mut String, plan: &WorkflowPlan, registry: &ToolRegistry) {
+    let commands: Vec<String> = plan
+        .tasks
+        .iter()
+        .map(|task| command(task, registry))
+        .collect();
+    script.push_str(&commands.join(" \\\n    | "));
+
+    let last = plan.tasks.last().expect("plan is not empty");
+    let accepted: Vec<String> = ok_codes(last, registry)
+        .iter()
+        .filter(|&&code| code != 0)
+        .map(i32::to_string)
+        .collect();
+    if !accepted.is_empty() {
+        let _ = write!(
+            script,
+            " \\\n    || case $? in {}) ;; *) exit 1 ;; esac",
+            accepted.join("|")
+        );
+    }
+    script.push('\n');
+}
+
+fn task_by_task(
+    script: &mut String,
+    plan: &WorkflowPlan,
+    graph: &PlanGraph,
+    registry: &ToolRegistry,
+) {
+    let number = |index: usize| plan.tasks[index].task_number;
+
+    script.push_str(TASK_HELPERS);
+    if (0..plan.tasks.len()).any(|index| graph.reads_plan_input(index)) {
+        script.push_str("\ncat > \"$tmp/input\"\n");
+    }
+
+    for &index in graph.order() {
+        let task = &plan.tasks[index];
+        let _ = writeln!(
+            script,
+            "\n# Task {}: {}",
+            task.task_number,
+            std::iter::once(task.command.as_str())
+                .chain(task.args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ")
+                .replace('\n', " ")
+        );
+
+        let mut guards: Vec<String> = graph
+            .sources(index)
+            .iter()
+            .map(|&source| format!("ok {}", number(source)))
+            .collect();
+        if let Some(condition) = &task.run_if {
+            guards.push(condition_test(condition));
+        }
+        if let Some(replaced) = graph.fallback_for(index) {
+            guards.push(format!("failed {}", number(replaced)));
+        }
+
+        let indent = if guards.is_empty() { "" } else { "    " };
+        if !guards.is_empty() {
+            let _ = writeln!(script, "if {}; then", guards.join(" && "));
+        }
+
+        let input = match graph.sources(index) {
+            [] => "\"$tmp/input\"".to_string(),
+            [source] => format!("\"$tmp/{}\"", number(*source)),
+            sources => {
+                let files: Vec<String> = sources
+                    .iter()
+                    .map(|&source| format!("\"$tmp/{}\"", number(source)))
+                    .collect();
+                let _ = writeln!(
+                    script,
+                    "{indent}cat {} > \"$tmp/{}.in\"",
+                    files.join(" "),
+                    task.task_number
+                );
+                format!("\"$tmp/{}.in\"", task.task_number)
+            }
+        };
+
+        let codes: Vec<String> = ok_codes(task, registry)
+            .iter()
+            .map(i32::to_string)
+            .collect();
+        let run = format!(
+            "run {} \"{}\" {} < {input} > \"$tmp/{}\"",
+            task.task_number,
+            codes.join(" "),
+            command(task, registry),
+            task.task_number
+        );
+        let on_failure = if task.on_failure == FailurePolicy::Stop {
+            "exit 1"
+        } else {
+            "true"
+        };
+
+        match &task.retry {
+            None => {
+                let _ = writeln!(script, "{indent}{run} || {on_failure}");
+            }
+            Some(retry) => {
+                let waits: Vec<String> = (1..retry.max_attempts.max(1))
+                    .map(|attempt| seconds(retry.backoff(attempt)))
+                    .chain(std::iter::once("last".to_string()))
+                    .collect();
+                let status = format!("\"$status_{}\"", task.task_number);
+                // Timeouts are never retried
+                let retryable = if retry.retryable_exit_codes.is_empty() {
+                    format!("[ {status} -ne 124 ]")
+                } else {
+                    let codes: Vec<String> = retry
+                        .retryable_exit_codes
+                        .iter()
+                        .map(i32::to_string)
+                        .collect();
+                    format!(
+                        "case {status} in {}) true ;; *) false ;; esac",
+                        codes.join("|")
+                    )
+                };
+                let give_up = if task.on_failure == FailurePolicy::Stop {
+                    "exit 1"
+                } else {
+                    "break"
+                };
+
+                let _ = writeln!(script, "{indent}for wait in {}; do", waits.join(" "));
+                let _ = writeln!(script, "{indent}    if {run}; then break; fi");
+                let _ = writeln!(
+                    script,
+                    "{indent}    if [ \"$wait\" = last ] || ! {retryable}; then {give_up}; fi"
+                );
+                let _ = writeln!(script, "{indent}    sleep \"$wait\"");
+                let _ = writeln!(script, "{indent}done");
+            }
+        }
+
+        if !guards.is_empty() {
+            script.push_str("fi\n");
+        }
+    }
+
+    let sinks: Vec<String> = graph
+        .sinks()
+        .into_iter()
+        .map(|index| number(index).to_string())
+        .collect();
+    let _ = write!(
+        script,
+        "\n# Outputs no task reads, in task order\n\
+         for task in {}; do\n    if ok \"$task\"; then cat \"$tmp/$task\"; fi\ndone\n",
+        sinks.join(" ")
+    );
+}
+
+/// The task's command line, wrapped in `timeout`
+fn command(task: &PlanStep, registry: &ToolRegistry) -> String {
+    let program = registry
+        .find_by_id(&task.command)
+        .map(|tool| tool.command)
+        .unwrap_or(&task.command);
+
+    std::iter::once(format!("timeout {}", task.timeout_secs))
+        .chain(std::iter::once(quote(program)))
+        .chain(task.args.iter().map(|arg| quote(arg)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn ok_codes(task: &PlanStep, registry: &ToolRegistry) -> &'static [i32] {
+    registry
+        .find_by_id(&task.command)
+        .map(|tool| tool.ok_exit_codes)
+        .unwrap_or(&[0])
+}
+
+/// A test that holds when `condition` does, as in `PLAN run`: `exit_code`
+/// also looks at a task that failed but not at one that was skipped, while
+/// the output conditions only hold for a task that succeeded. Unlike in
+/// `PLAN run`, a task stopped by `timeout` has exit status 124.
+/// `output_matches` uses `grep -E`, whose syntax is close to, but not the
+/// same as, the plan's.
+fn condition_test(condition: &RunCondition) -> String {
+    let task = condition.task();
+
+    let test = match condition {
+        // `status_N` is only set once the task has run
+        RunCondition::ExitCode { equals, .. } => {
+            return format!("[ \"${{status_{task}:-}}\" = {equals} ]");
+        }
+        RunCondition::OutputEmpty { .. } => format!("[ ! -s \"$tmp/{task}\" ]"),
+        RunCondition::OutputNotEmpty { .. } => format!("[ -s \"$tmp/{task}\" ]"),
+        RunCondition::OutputMatches { pattern, .. } => {
+            format!("grep -Eq -- {} \"$tmp/{task}\"", quote(pattern))
+        }
+    };
+
+    format!("ok {task} && {test}")
+}
+
+/// `duration` as an argument to `sleep`
+fn seconds(duration: std::time::Duration) -> String {
+    let millis = duration.as_millis();
+
+    if millis % 1000 == 0 {
+        (millis / 1000).to_string()
+    } else {
+        format!("{}.{:03}", millis / 1000, millis % 1000)
+            .trim_end_matches('0')
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{ExecutionInput, Executor};
+    use crate::plan::{step, RetryPolicy};
+
+    fn plan(tasks: Vec<PlanStep>) -> WorkflowPlan {
+        WorkflowPlan {
+            tasks,
+            ..Default::default()
+        }
+    }
+
+    fn script(plan: &WorkflowPlan) -> String {
+        export(plan, ExportFormat::Sh, &ToolRegistry::new()).unwrap()
+    }
+
+    /// Run the exported script and `PLAN run` on the same input
+    fn outputs(plan: &WorkflowPlan, input: &str) -> (String, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.sh");
+        std::fs::write(&path, script(plan)).unwrap();
+        let input_path = dir.path().join("input");
+        std::fs::write(&input_path, input).unwrap();
+
+        let shell = std::process::Command::new("sh")
+            .arg(&path)
+            .stdin(std::fs::File::open(&input_path).unwrap())
+            .output()
+            .unwrap();
+        assert!(
+            shell.status.success(),
+            "{}",
+            String::from_utf8_lossy(&shell.stderr)
+        );
+
+        let executor = Executor::new();
+        let mut output = Vec::new();
+        executor
+            .execute_to(
+                plan,
+                &ExecutionInput::File(input_path),
+                &ToolRegistry::new(),
+                &mut output,
+            )
+            .unwrap();
+
+        (
+            String::from_utf8(shell.stdout).unwrap(),
+            String::from_utf8(output).unwrap(),
+        )
+    }
+
+    #[test]
+    fn chains_become_a_pipeline() {
+        let mut chain = plan(vec![
+            step(1, "cut", &["-d", " ", "-f3"], None),
+            step(2, "sort", &[], None),
+            step(3, "grep", &["-v", "it's"], None),
+        ]);
+        chain.plan_description = Some("Third fields".to_string());
+        chain.tasks[1].timeout_secs = 60;
+
+        assert_eq!(
+            script(&chain),
+            "#!/bin/sh\n\
+             # Third fields\n\
+             # Exported from an agx plan. Reads the plan input on STDIN and writes the\n\
+             # plan output to STDOUT; each task is stopped after its timeout.\n\
+             set -eu\n\
+             \n\
+             timeout 300 cut -d ' ' -f3 \\\n    \
+             | timeout 60 sort \\\n    \
+             | timeout 300 grep -v 'it'\\''s' \\\n    \
+             || case $? in 1) ;; *) exit 1 ;; esac\n"
+        );
+    }
+
+    #[test]
+    fn graphs_match_plan_run() {
+        let mut graph = plan(vec![
+            step(1, "grep", &["ERROR"], None),
+            step(2, "grep", &["WARN"], None),
+            step(3, "sort", &["-r"], None),
+            step(4, "grep", &["-c", "."], None),
+            step(5, "tr", &["a-z", "A-Z"], Some(2)),
+        ]);
+        graph.tasks[2].inputs_from_tasks = vec![1, 2];
+        graph.tasks[3].input_from_task = Some(3);
+        graph.tasks[4].run_if = Some(RunCondition::OutputMatches {
+            task: 1,
+            pattern: "disk".to_string(),
+        });
+
+        let text = script(&graph);
+        assert!(text.contains("cat \"$tmp/1\" \"$tmp/2\" > \"$tmp/3.in\""));
+        assert!(text.contains("if ok 2 && ok 1 && grep -Eq -- disk \"$tmp/1\"; then"));
+
+        let input = "ERROR disk full\nWARN slow\nINFO ok\nERROR again\n";
+        let (shell, run) = outputs(&graph, input);
+        assert_eq!(shell, run);
+        assert_eq!(shell, "3\nWARN SLOW\n");
+    }
+
+    #[test]
+    fn failures_and_retries_follow_the_plan() {
+        let mut tasks = vec![
+            step(1, "grep", &["-E", "("], None),
+            step(2, "tr", &["a-z", "A-Z"], None),
+            step(3, "sort", &[], None),
+        ];
+        tasks[0].on_failure = FailurePolicy::Fallback(2);
+        tasks[2].input_from_task = Some(2);
+        tasks[2].retry = Some(RetryPolicy {
+            max_attempts: 3,
+            backoff_base_ms: 500,
+            backoff_max_ms: 30_000,
+            retryable_exit_codes: vec![75],
+        });
+        let fallback = plan(tasks);
+
+        let text = script(&fallback);
+        assert!(text.contains("if failed 1; then"));
+        assert!(text.contains("for wait in 0.5 1 last; do"));
+        assert!(text.contains("case \"$status_3\" in 75) true ;; *) false ;; esac"));
+
+        let (shell, run) = outputs(&fallback, "b\na\n");
+        assert_eq!(shell, run);
+        assert_eq!(shell, "A\nB\n");
+    }
+
+    #[test]
+    fn exit_code_conditions_see_failed_tasks() {
+        let mut tasks = vec![
+            step(1, "grep", &["--no-such-flag"], None),
+            step(2, "tr", &["a-z", "A-Z"], None),
+            step(3, "sort", &[], None),
+            step(4, "cut", &["-c1"], Some(1)),
+        ];
+        tasks[0].on_failure = FailurePolicy::Continue;
+        tasks[1].run_if = Some(RunCondition::ExitCode { task: 1, equals: 2 });
+        tasks[2].run_if = Some(RunCondition::OutputEmpty { task: 1 });
+        let conditions = plan(tasks);
+
+        let text = script(&conditions);
+        assert!(text.contains("if [ \"${status_1:-}\" = 2 ]; then"));
+        assert!(text.contains("if ok 1 && [ ! -s \"$tmp/1\" ]; then"));
+
+        let (shell, run) = outputs(&conditions, "a\n");
+        assert_eq!(shell, run);
+        assert_eq!(shell, "A\n");
+    }
+}
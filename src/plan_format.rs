@@ -0,0 +1,258 @@
+//! File formats of plans. Plans go to AGQ as JSON, and the buffer is JSON
+//! by default, but hand-written plans read better as YAML or TOML, where
+//! awk programs and regular expressions need no escaped quotes. A file's
+//! format follows its extension: `.yaml` or `.yml`, `.toml`, and JSON for
+//! anything else.
+//!
+//! Whatever its format, a plan document goes through the same schema
+//! migrations as a JSON one, so legacy shapes are accepted in all three.
+//! TOML has no null, so plans with a null anywhere (in practice, in an
+//! `input_schema`) cannot be written as TOML.
+
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::plan::WorkflowPlan;
+use crate::plan_migrate;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl PlanFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "json" => Some(PlanFormat::Json),
+            "yaml" | "yml" => Some(PlanFormat::Yaml),
+            "toml" => Some(PlanFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// The format of the plan file at `path`, by its extension
+    pub fn from_path(path: &Path) -> Self {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(PlanFormat::parse)
+            .unwrap_or(PlanFormat::Json)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PlanFormat::Json => "JSON",
+            PlanFormat::Yaml => "YAML",
+            PlanFormat::Toml => "TOML",
+        }
+    }
+}
+
+/// Read a plan document written in `format` as JSON, before migration
+pub fn read_document(text: &str, format: PlanFormat) -> Result<Value, String> {
+    match format {
+        PlanFormat::Json => serde_json::from_str(text).map_err(|error| error.to_string()),
+        PlanFormat::Yaml => serde_yaml::from_str(text).map_err(|error| error.to_string()),
+        PlanFormat::Toml => toml::from_str(text).map_err(|error| error.to_string()),
+    }
+}
+
+/// Parse a stored plan written in `format`, migrating it to the current
+/// schema version first
+pub fn parse_plan(text: &str, format: PlanFormat) -> Result<WorkflowPlan, String> {
+    let document = read_document(text, format)?;
+    let (document, _) = plan_migrate::migrate(document)?;
+
+    serde_json::from_value(document).map_err(|error| error.to_string())
+}
+
+/// Read and parse the plan file at `path`, in the format of its extension
+pub fn load_plan_file(path: &Path) -> Result<WorkflowPlan, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|error| format!("failed to read plan file {}: {error}", path.display()))?;
+
+    parse_plan(&contents, PlanFormat::from_path(path))
+        .map_err(|error| format!("failed to parse plan file {}: {error}", path.display()))
+}
+
+/// `plan` written in `format`, ending with a newline
+pub fn write_plan(plan: &WorkflowPlan, format: PlanFormat) -> Result<String, String> {
+    write_document(plan, format)
+}
+
+/// A plan document, typed or not, written in `format`
+pub fn write_document<T: Serialize>(document: &T, format: PlanFormat) -> Result<String, String> {
+    let failed = |error: &dyn std::fmt::Display| {
+        format!("failed to write plan as {}: {error}", format.name())
+    };
+
+    match format {
+        PlanFormat::Json => serde_json::to_string_pretty(document)
+            .map(|json| json + "\n")
+            .map_err(|error| failed(&error)),
+        PlanFormat::Yaml => {
+            // `on_failure: {fallback: 4}` as in JSON, rather than a YAML tag
+            let mut yaml = Vec::new();
+            let mut serializer = serde_yaml::Serializer::new(&mut yaml);
+            serde_yaml::with::singleton_map_recursive::serialize(document, &mut serializer)
+                .map_err(|error| failed(&error))?;
+
+            String::from_utf8(yaml).map_err(|error| failed(&error))
+        }
+        PlanFormat::Toml => {
+            // Dropping the null would change what the plan means
+            let value = serde_json::to_value(document).map_err(|error| failed(&error))?;
+            if let Some(path) = null_path(&value, String::new()) {
+                return Err(failed(&format!(
+                    "TOML has no null, but `{path}` is null; use YAML or JSON for this plan"
+                )));
+            }
+
+            toml::to_string_pretty(document).map_err(|error| failed(&error))
+        }
+    }
+}
+
+/// Where the first null in `value` is, as a dotted path below `path`
+fn null_path(value: &Value, path: String) -> Option<String> {
+    match value {
+        Value::Null => Some(path),
+        Value::Array(items) => items
+            .iter()
+            .enumerate()
+            .find_map(|(index, item)| null_path(item, format!("{path}[{index}]"))),
+        Value::Object(fields) => fields.iter().find_map(|(key, field)| {
+            let path = if path.is_empty() {
+                key.clone()
+            } else {
+                format!("{path}.{key}")
+            };
+            null_path(field, path)
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{step, FailurePolicy, RetryPolicy, RunCondition};
+
+    fn sample_plan() -> WorkflowPlan {
+        let mut plan = WorkflowPlan {
+            plan_description: Some("Count fatal errors".to_string()),
+            tasks: ["grep", "cut", "sort"]
+                .into_iter()
+                .zip(1..)
+                .map(|(command, task_number)| step(task_number, command, &[], None))
+                .collect(),
+            ..Default::default()
+        };
+        plan.tasks[0].args = vec!["-E".to_string(), r#""level": "(FATAL|ERROR)""#.to_string()];
+        plan.tasks[0].on_failure = FailurePolicy::Fallback(2);
+        plan.tasks[1].args = vec!["-d".to_string(), "'".to_string(), "-f2".to_string()];
+        plan.tasks[2].inputs_from_tasks = vec![1, 2];
+        plan.tasks[2].run_if = Some(RunCondition::OutputNotEmpty { task: 1 });
+        plan.tasks[2].retry = Some(RetryPolicy {
+            max_attempts: 3,
+            backoff_base_ms: 500,
+            backoff_max_ms: 2000,
+            retryable_exit_codes: vec![75],
+        });
+        plan
+    }
+
+    #[test]
+    fn formats_follow_the_extension() {
+        for (path, format) in [
+            ("plan.yaml", PlanFormat::Yaml),
+            ("plan.YML", PlanFormat::Yaml),
+            ("plans/plan.toml", PlanFormat::Toml),
+            ("plan.json", PlanFormat::Json),
+            ("agx-plan", PlanFormat::Json),
+        ] {
+            assert_eq!(PlanFormat::from_path(Path::new(path)), format, "{path}");
+        }
+    }
+
+    #[test]
+    fn plans_round_trip_in_every_format() {
+        let plan = sample_plan();
+        let expected = serde_json::to_value(&plan).unwrap();
+
+        for format in [PlanFormat::Json, PlanFormat::Yaml, PlanFormat::Toml] {
+            let text = write_plan(&plan, format).unwrap();
+            assert!(text.ends_with('\n'), "{text}");
+
+            let parsed = parse_plan(&text, format).unwrap();
+            assert_eq!(serde_json::to_value(&parsed).unwrap(), expected, "{text}");
+        }
+    }
+
+    #[test]
+    fn reads_hand_written_plans() {
+        let yaml = r#"
+tasks:
+  - task_number: 1
+    command: grep
+    args: ["-E", '"level": "(FATAL|ERROR)"']
+    on_failure: {fallback: 2}
+  - task_number: 2
+    command: sort
+"#;
+        let toml = r#"
+[[tasks]]
+task_number = 1
+command = "grep"
+args = ["-E", '"level": "(FATAL|ERROR)"']
+on_failure = { fallback = 2 }
+
+[[tasks]]
+task_number = 2
+command = "sort"
+"#;
+
+        for (text, format) in [(yaml, PlanFormat::Yaml), (toml, PlanFormat::Toml)] {
+            let plan = parse_plan(text, format).unwrap();
+            assert_eq!(plan.tasks.len(), 2);
+            assert_eq!(plan.tasks[0].args[1], r#""level": "(FATAL|ERROR)""#);
+            assert_eq!(plan.tasks[0].on_failure, FailurePolicy::Fallback(2));
+            assert_eq!(plan.tasks[1].timeout_secs, 300);
+        }
+
+        // Legacy 0.1 documents are migrated in YAML too
+        let legacy =
+            parse_plan("plan:\n  - cmd: sort\n    args: [-r]\n", PlanFormat::Yaml).unwrap();
+        assert_eq!(legacy.tasks[0].command, "sort");
+        assert_eq!(legacy.tasks[0].args, ["-r"]);
+
+        assert!(parse_plan("tasks = [", PlanFormat::Toml).is_err());
+    }
+
+    #[test]
+    fn toml_rejects_nulls() {
+        let mut plan = sample_plan();
+        plan.input_schema = Some(serde_json::json!({
+            "type": "object",
+            "properties": {"level": {"type": ["string", "null"], "default": null}}
+        }));
+
+        let error = write_plan(&plan, PlanFormat::Toml).unwrap_err();
+        assert_eq!(
+            error,
+            "failed to write plan as TOML: TOML has no null, but \
+             `input_schema.properties.level.default` is null; use YAML or JSON for this plan"
+        );
+
+        for format in [PlanFormat::Json, PlanFormat::Yaml] {
+            let text = write_plan(&plan, format).unwrap();
+            assert_eq!(
+                parse_plan(&text, format).unwrap().input_schema,
+                plan.input_schema
+            );
+        }
+    }
+}
@@ -0,0 +1,384 @@
+//! Data-flow graph of a plan, derived from `input_from_task` and
+//! `inputs_from_tasks` references.
+//!
+//! A plan where no task declares a reference is a legacy linear pipeline:
+//! every task reads the previous task's stdout and the first task reads the
+//! plan input. As soon as one task declares a reference, the plan is treated
+//! as an explicit graph and tasks without a reference read the plan input.
+//! A task reading several tasks gets their outputs concatenated in the order
+//! listed. References may point at later tasks as long as they form no cycle.
+//!
+//! Besides its inputs, a task waits for the task its `run_if` condition looks
+//! at, and a fallback task waits for the task whose `on_failure` names it.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::plan::{FailurePolicy, WorkflowPlan};
+
+#[derive(Debug, Clone)]
+pub struct PlanGraph {
+    /// Positions whose outputs feed each task; empty for the plan input
+    sources: Vec<Vec<usize>>,
+    consumers: Vec<Vec<usize>>,
+    /// Position of the task each `run_if` looks at
+    conditions: Vec<Option<usize>>,
+    /// Position of the task each fallback task stands in for
+    fallback_for: Vec<Option<usize>>,
+    order: Vec<usize>,
+}
+
+impl PlanGraph {
+    pub fn build(plan: &WorkflowPlan) -> Result<Self, String> {
+        let positions: HashMap<u32, usize> = plan
+            .tasks
+            .iter()
+            .enumerate()
+            .map(|(index, task)| (task.task_number, index))
+            .collect();
+
+        let references = plan
+            .tasks
+            .iter()
+            .map(|task| task.input_tasks())
+            .collect::<Result<Vec<Vec<u32>>, String>>()?;
+
+        let explicit = references.iter().any(|references| !references.is_empty());
+        let mut sources = Vec::with_capacity(plan.tasks.len());
+        let mut consumers = vec![Vec::new(); plan.tasks.len()];
+
+        for (index, task) in plan.tasks.iter().enumerate() {
+            let task_sources = if explicit {
+                references[index]
+                    .iter()
+                    .map(|reference| {
+                        positions.get(reference).copied().ok_or_else(|| {
+                            format!(
+                                "task {} reads input from unknown task {}",
+                                task.task_number, reference
+                            )
+                        })
+                    })
+                    .collect::<Result<Vec<usize>, String>>()?
+            } else if index == 0 {
+                Vec::new()
+            } else {
+                vec![index - 1]
+            };
+
+            for &source in &task_sources {
+                consumers[source].push(index);
+            }
+
+            sources.push(task_sources);
+        }
+
+        let reference = |task: u32, field: &str, reference: u32| {
+            positions
+                .get(&reference)
+                .copied()
+                .ok_or_else(|| format!("task {task} {field} references unknown task {reference}"))
+        };
+
+        let mut conditions = vec![None; plan.tasks.len()];
+        let mut fallback_for = vec![None; plan.tasks.len()];
+
+        for (index, task) in plan.tasks.iter().enumerate() {
+            if let Some(condition) = &task.run_if {
+                condition
+                    .validate()
+                    .map_err(|error| format!("task {}: {error}", task.task_number))?;
+                conditions[index] = Some(reference(task.task_number, "run_if", condition.task())?);
+            }
+
+            if let FailurePolicy::Fallback(fallback) = task.on_failure {
+                let fallback = reference(task.task_number, "on_failure", fallback)?;
+
+                if let Some(other) = fallback_for[fallback].replace(index) {
+                    return Err(format!(
+                        "task {} is the fallback of both task {} and task {}",
+                        plan.tasks[fallback].task_number,
+                        plan.tasks[other].task_number,
+                        task.task_number
+                    ));
+                }
+
+                // The output of a failed task is never read
+                if sources[fallback].contains(&index) {
+                    return Err(format!(
+                        "task {} cannot read the output of task {}, which it is the fallback of",
+                        plan.tasks[fallback].task_number, task.task_number
+                    ));
+                }
+            }
+        }
+
+        let mut graph = Self {
+            sources,
+            consumers,
+            conditions,
+            fallback_for,
+            order: Vec::new(),
+        };
+
+        let dependencies: Vec<Vec<usize>> = (0..plan.tasks.len())
+            .map(|index| graph.dependencies(index).collect())
+            .collect();
+        graph.order = topological_order(&dependencies).map_err(|index| {
+            format!(
+                "task {} is part of a dependency cycle",
+                plan.tasks[index].task_number
+            )
+        })?;
+
+        Ok(graph)
+    }
+
+    /// Positions of the tasks whose outputs are concatenated into the STDIN
+    /// of task `index`; empty when it reads the plan input
+    pub fn sources(&self, index: usize) -> &[usize] {
+        &self.sources[index]
+    }
+
+    pub fn reads_plan_input(&self, index: usize) -> bool {
+        self.sources[index].is_empty()
+    }
+
+    pub fn consumers(&self, index: usize) -> &[usize] {
+        &self.consumers[index]
+    }
+
+    /// The task whose outcome decides whether task `index` runs
+    pub fn condition(&self, index: usize) -> Option<usize> {
+        self.conditions[index]
+    }
+
+    /// The task that task `index` runs in place of, when it fails
+    pub fn fallback_for(&self, index: usize) -> Option<usize> {
+        self.fallback_for[index]
+    }
+
+    /// Whether a `run_if` looks at the outcome of task `index`
+    pub fn is_condition(&self, index: usize) -> bool {
+        self.conditions.contains(&Some(index))
+    }
+
+    /// Tasks that have to finish before task `index` can start
+    pub fn dependencies(&self, index: usize) -> impl Iterator<Item = usize> + '_ {
+        self.sources[index]
+            .iter()
+            .chain(&self.conditions[index])
+            .chain(&self.fallback_for[index])
+            .copied()
+    }
+
+    /// Every position, each after all of its dependencies. Tasks keep their
+    /// plan order wherever the references allow it.
+    pub fn order(&self) -> &[usize] {
+        &self.order
+    }
+
+    /// Tasks whose output no other task consumes, in plan order
+    pub fn sinks(&self) -> Vec<usize> {
+        (0..self.consumers.len())
+            .filter(|&index| self.consumers[index].is_empty())
+            .collect()
+    }
+}
+
+/// Order positions so that each comes after all of its `sources`, preferring
+/// the lowest position among those that are ready. On a cycle, returns a
+/// position that is part of it.
+pub fn topological_order(sources: &[Vec<usize>]) -> Result<Vec<usize>, usize> {
+    let mut waiting: Vec<usize> = sources.iter().map(Vec::len).collect();
+    let mut consumers = vec![Vec::new(); sources.len()];
+    for (index, task_sources) in sources.iter().enumerate() {
+        for &source in task_sources {
+            consumers[source].push(index);
+        }
+    }
+
+    let mut ready: BinaryHeap<Reverse<usize>> = (0..sources.len())
+        .filter(|&index| waiting[index] == 0)
+        .map(Reverse)
+        .collect();
+    let mut order = Vec::with_capacity(sources.len());
+
+    while let Some(Reverse(index)) = ready.pop() {
+        order.push(index);
+
+        for &consumer in &consumers[index] {
+            waiting[consumer] -= 1;
+            if waiting[consumer] == 0 {
+                ready.push(Reverse(consumer));
+            }
+        }
+    }
+
+    if order.len() == sources.len() {
+        return Ok(order);
+    }
+
+    // Every task left waits on another task left; walking those references
+    // backwards must come back to a task already seen
+    let mut seen = vec![false; sources.len()];
+    let mut index = (0..sources.len())
+        .find(|&index| waiting[index] > 0)
+        .expect("a task is left waiting");
+
+    while !seen[index] {
+        seen[index] = true;
+        index = sources[index]
+            .iter()
+            .copied()
+            .find(|&source| waiting[source] > 0)
+            .expect("a waiting task waits on another waiting task");
+    }
+
+    Err(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{step, FailurePolicy, PlanStep, RunCondition};
+
+    fn fan_in(task_number: u32, command: &str, inputs: &[u32]) -> PlanStep {
+        PlanStep {
+            inputs_from_tasks: inputs.to_vec(),
+            ..step(task_number, command, &[], None)
+        }
+    }
+
+    fn plan(tasks: Vec<PlanStep>) -> WorkflowPlan {
+        WorkflowPlan {
+            tasks,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn unlinked_plan_is_linear() {
+        let graph = PlanGraph::build(&plan(vec![
+            step(1, "sort", &[], None),
+            step(2, "uniq", &[], None),
+        ]))
+        .unwrap();
+
+        assert!(graph.reads_plan_input(0));
+        assert_eq!(graph.sources(1), &[0]);
+        assert_eq!(graph.sinks(), vec![1]);
+    }
+
+    #[test]
+    fn explicit_references_allow_fan_out() {
+        let graph = PlanGraph::build(&plan(vec![
+            step(1, "grep", &[], None),
+            step(2, "sort", &[], Some(1)),
+            step(3, "grep", &[], None),
+        ]))
+        .unwrap();
+
+        assert!(graph.reads_plan_input(2));
+        assert_eq!(graph.consumers(0), &[1]);
+        assert_eq!(graph.sinks(), vec![1, 2]);
+        assert_eq!(graph.order(), &[0, 1, 2]);
+    }
+
+    #[test]
+    fn fan_in_reads_several_tasks_in_listed_order() {
+        let graph = PlanGraph::build(&plan(vec![
+            step(1, "grep", &[], None),
+            step(2, "grep", &[], None),
+            fan_in(3, "sort", &[2, 1]),
+        ]))
+        .unwrap();
+
+        assert_eq!(graph.sources(2), &[1, 0]);
+        assert_eq!(graph.consumers(0), &[2]);
+        assert_eq!(graph.sinks(), vec![2]);
+    }
+
+    #[test]
+    fn forward_references_run_after_their_sources() {
+        let graph = PlanGraph::build(&plan(vec![
+            step(1, "sort", &[], Some(3)),
+            step(2, "grep", &[], None),
+            step(3, "grep", &[], None),
+        ]))
+        .unwrap();
+
+        assert_eq!(graph.order(), &[1, 2, 0]);
+    }
+
+    #[test]
+    fn rejects_cycles_and_unknown_references() {
+        let cycle = PlanGraph::build(&plan(vec![
+            step(1, "grep", &[], None),
+            step(2, "sort", &[], Some(3)),
+            fan_in(3, "uniq", &[1, 2]),
+        ]));
+        assert!(cycle.unwrap_err().contains("dependency cycle"));
+
+        let unknown = PlanGraph::build(&plan(vec![step(1, "sort", &[], Some(7))]));
+        assert!(unknown.unwrap_err().contains("unknown task 7"));
+
+        let both = PlanGraph::build(&plan(vec![
+            step(1, "grep", &[], None),
+            PlanStep {
+                inputs_from_tasks: vec![1],
+                ..step(2, "sort", &[], Some(1))
+            },
+        ]));
+        assert!(both.unwrap_err().contains("sets both"));
+    }
+
+    #[test]
+    fn conditions_and_fallbacks_order_tasks() {
+        let graph = PlanGraph::build(&plan(vec![
+            PlanStep {
+                run_if: Some(RunCondition::OutputEmpty { task: 3 }),
+                ..step(1, "sort", &[], Some(3))
+            },
+            step(2, "grep", &[], None),
+            PlanStep {
+                on_failure: FailurePolicy::Fallback(2),
+                ..step(3, "grep", &[], None)
+            },
+        ]))
+        .unwrap();
+
+        assert_eq!(graph.condition(0), Some(2));
+        assert!(graph.is_condition(2));
+        assert_eq!(graph.fallback_for(1), Some(2));
+        assert_eq!(graph.order(), &[2, 0, 1]);
+    }
+
+    #[test]
+    fn rejects_fallbacks_reading_their_task() {
+        let error = PlanGraph::build(&plan(vec![
+            PlanStep {
+                on_failure: FailurePolicy::Fallback(2),
+                ..step(1, "grep", &[], None)
+            },
+            step(2, "sort", &[], None),
+        ]))
+        .unwrap_err();
+
+        assert!(
+            error.contains("cannot read the output of task 1"),
+            "{error}"
+        );
+
+        let error = PlanGraph::build(&plan(vec![PlanStep {
+            run_if: Some(RunCondition::OutputMatches {
+                task: 1,
+                pattern: "[".to_string(),
+            }),
+            ..step(1, "grep", &[], None)
+        }]))
+        .unwrap_err();
+
+        assert!(error.contains("invalid output_matches pattern"), "{error}");
+    }
+}
@@ -0,0 +1,256 @@
+//! `PLAN import --pipeline`: turn a shell pipeline the user already knows
+//! into a plan directly, without asking a planner for it.
+//!
+//! The pipeline is split the way a POSIX shell would split it: words are
+//! separated by blanks, single quotes keep everything literally, double
+//! quotes keep everything but `\"`, `\\`, `\$` and `` \` `` escapes, and a
+//! backslash outside quotes escapes the next character. Anything beyond
+//! commands joined by `|` (redirections, `;`, `&&`, `$` expansions) is
+//! rejected rather than passed through as literal arguments, and so are
+//! unquoted characters the shell would expand: glob patterns (`*`, `?`,
+//! `[`), braces, and `~` or `#` at the start of a word.
+
+use crate::plan::{FailurePolicy, PlanStep, WorkflowPlan};
+use crate::registry::ToolRegistry;
+
+/// A plan running the commands of `pipeline` in turn, each reading the
+/// output of the one before it. Every command has to be a registered tool.
+pub fn import_pipeline(pipeline: &str, registry: &ToolRegistry) -> Result<WorkflowPlan, String> {
+    let stages = tokenize(pipeline)?;
+
+    let tasks = stages
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut words)| {
+            let task_number = index as u32 + 1;
+            let command = words.remove(0);
+
+            if registry.find_by_id(&command).is_none() {
+                let known: Vec<&str> = registry.tools().iter().map(|tool| tool.id).collect();
+                return Err(format!(
+                    "unknown tool `{command}` in pipeline stage {task_number} (known tools: {})",
+                    known.join(", ")
+                ));
+            }
+
+            Ok(PlanStep {
+                task_number,
+                command,
+                args: words,
+                timeout_secs: 300,
+                input_from_task: (task_number > 1).then(|| task_number - 1),
+                inputs_from_tasks: Vec::new(),
+                run_if: None,
+                on_failure: FailurePolicy::Stop,
+                retry: None,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    Ok(WorkflowPlan {
+        tasks,
+        ..Default::default()
+    })
+}
+
+/// Split `pipeline` into the words of each of its commands
+fn tokenize(pipeline: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut stages = Vec::new();
+    let mut words = Vec::new();
+    // `None` between words, so that `''` still makes an (empty) word
+    let mut word: Option<String> = None;
+    let mut chars = pipeline.char_indices().peekable();
+
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => words.extend(word.take()),
+            '|' => {
+                if chars.peek().is_some_and(|&(_, next)| next == '|') {
+                    return Err(unsupported("||", offset));
+                }
+                words.extend(word.take());
+                if words.is_empty() {
+                    return Err(format!("empty command before `|` at offset {offset}"));
+                }
+                stages.push(std::mem::take(&mut words));
+            }
+            '\'' => {
+                let text = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some((_, '\'')) => break,
+                        Some((_, c)) => text.push(c),
+                        None => return Err(format!("unterminated ' quote at offset {offset}")),
+                    }
+                }
+            }
+            '"' => {
+                let text = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.peek() {
+                            Some(&(_, escaped @ ('"' | '\\' | '$' | '`'))) => {
+                                text.push(escaped);
+                                chars.next();
+                            }
+                            Some(&(_, '\n')) => {
+                                chars.next();
+                            }
+                            _ => text.push('\\'),
+                        },
+                        Some((position, symbol @ ('$' | '`'))) => {
+                            return Err(unsupported(&symbol.to_string(), position));
+                        }
+                        Some((_, c)) => text.push(c),
+                        None => return Err(format!("unterminated \" quote at offset {offset}")),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // Line continuation
+                Some((_, '\n')) => {}
+                Some((_, escaped)) => word.get_or_insert_with(String::new).push(escaped),
+                None => return Err("trailing backslash at the end of the pipeline".to_string()),
+            },
+            ';' | '&' | '<' | '>' | '(' | ')' | '`' | '$' => {
+                return Err(unsupported(&c.to_string(), offset));
+            }
+            '*' | '?' | '[' => return Err(expanded(c, "a glob pattern", offset)),
+            '{' => return Err(expanded(c, "brace expansion", offset)),
+            '~' if word.is_none() => return Err(expanded(c, "tilde expansion", offset)),
+            '#' if word.is_none() => return Err(expanded(c, "a comment", offset)),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+    if words.is_empty() {
+        return Err(if stages.is_empty() {
+            "pipeline is empty".to_string()
+        } else {
+            "pipeline ends with `|`".to_string()
+        });
+    }
+    stages.push(words);
+
+    Ok(stages)
+}
+
+fn unsupported(syntax: &str, offset: usize) -> String {
+    format!(
+        "unsupported shell syntax `{syntax}` at offset {offset}: only commands joined by `|` can be imported (quote it to pass it as an argument)"
+    )
+}
+
+fn expanded(symbol: char, what: &str, offset: usize) -> String {
+    format!(
+        "unquoted `{symbol}` at offset {offset} starts {what}, which the shell would expand; quote it to pass it as a literal argument"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(pipeline: &str) -> Vec<Vec<String>> {
+        tokenize(pipeline).unwrap()
+    }
+
+    #[test]
+    fn imports_linked_tasks() {
+        let plan = import_pipeline(
+            "grep ERROR | cut -d' ' -f3 | sort | uniq -c",
+            &ToolRegistry::new(),
+        )
+        .unwrap();
+
+        let commands: Vec<(&str, Vec<&str>)> = plan
+            .tasks
+            .iter()
+            .map(|task| {
+                (
+                    task.command.as_str(),
+                    task.args.iter().map(String::as_str).collect(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                ("grep", vec!["ERROR"]),
+                ("cut", vec!["-d ", "-f3"]),
+                ("sort", vec![]),
+                ("uniq", vec!["-c"]),
+            ]
+        );
+
+        let inputs: Vec<Option<u32>> = plan.tasks.iter().map(|task| task.input_from_task).collect();
+        assert_eq!(inputs, [None, Some(1), Some(2), Some(3)]);
+    }
+
+    #[test]
+    fn rejects_unregistered_tools() {
+        let error = import_pipeline("grep x | wc -l", &ToolRegistry::new()).unwrap_err();
+        assert!(
+            error.starts_with("unknown tool `wc` in pipeline stage 2"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn follows_posix_quoting() {
+        assert_eq!(
+            words(r#"grep "a \"b\" \$c" 'x|y'"z" '' a\ b"#),
+            [vec!["grep", r#"a "b" $c"#, "x|yz", "", "a b"]]
+        );
+        assert_eq!(
+            words("tr -d '\\n'|sort"),
+            [vec!["tr", "-d", "\\n"], vec!["sort"]]
+        );
+        assert_eq!(words("sort \\\n  -r"), [vec!["sort", "-r"]]);
+    }
+
+    #[test]
+    fn rejects_other_shell_syntax() {
+        for pipeline in [
+            "sort > out",
+            "grep a; sort",
+            "grep a && sort",
+            "grep a || sort",
+            "grep $PATTERN",
+            "grep \"$(date)\"",
+            "sort <in",
+            "grep 'a",
+            "sort |",
+            "| sort",
+            "grep a | | sort",
+            "  ",
+        ] {
+            assert!(tokenize(pipeline).is_err(), "{pipeline}");
+        }
+    }
+
+    #[test]
+    fn rejects_unquoted_expansions() {
+        for (pipeline, symbol) in [
+            ("grep foo *.log", '*'),
+            ("grep -c ab? | sort", '?'),
+            ("tr [a-z] [A-Z]", '['),
+            ("cut -f{1,2}", '{'),
+            ("sort ~/words", '~'),
+            ("sort # reversed", '#'),
+        ] {
+            let error = tokenize(pipeline).unwrap_err();
+            assert!(
+                error.starts_with(&format!("unquoted `{symbol}` at offset")),
+                "{pipeline}: {error}"
+            );
+        }
+
+        assert_eq!(
+            words(r#"grep '*.log' "a?" \[x] a~b a#b"#),
+            [vec!["grep", "*.log", "a?", "[x]", "a~b", "a#b"]]
+        );
+    }
+}
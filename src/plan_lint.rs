@@ -0,0 +1,322 @@
+//! Static review of a plan for `PLAN lint` and `PLAN submit`. Where
+//! `PLAN check` asks whether the plan can run on this machine, the linter
+//! looks for plans that run but probably do not do what was meant: shell
+//! syntax passed as literal arguments, `uniq` over unsorted input, outputs
+//! nobody reads and the like. Each finding names the rule that produced it
+//! and a severity; `PLAN submit` refuses plans with errors.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::job::MAX_TASKS;
+use crate::plan::WorkflowPlan;
+use crate::plan_graph::PlanGraph;
+use crate::registry::ToolRegistry;
+
+/// Timeouts outside this range, in seconds, are reported
+const SHORTEST_TIMEOUT_SECS: u32 = 5;
+const LONGEST_TIMEOUT_SECS: u32 = 3600;
+
+/// Arguments that only mean something to a shell. Tasks are spawned
+/// directly, so these reach the tool as literal text.
+const SHELL_OPERATORS: &[&str] = &["|", "||", "&", "&&", ";", ";;", "(", ")"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warn,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Severity::Info => "info",
+            Severity::Warn => "warn",
+            Severity::Error => "error",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    /// Stable identifier of the check, such as `unknown-tool`
+    pub rule: &'static str,
+    pub severity: Severity,
+    /// The task concerned, unless the finding is about the whole plan
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_number: Option<u32>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:<5} {:<20} ", self.severity, self.rule)?;
+        if let Some(task_number) = self.task_number {
+            write!(f, "task {task_number}: ")?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+/// Findings for `plan`, most severe first and otherwise in task order
+pub fn lint(plan: &WorkflowPlan, registry: &ToolRegistry) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut report = |rule, severity, task_number, message| {
+        findings.push(Finding {
+            rule,
+            severity,
+            task_number,
+            message,
+        })
+    };
+
+    if plan.tasks.len() > MAX_TASKS {
+        report(
+            "too-many-tasks",
+            Severity::Error,
+            None,
+            format!(
+                "plan has {} tasks; AGQ accepts at most {MAX_TASKS}",
+                plan.tasks.len()
+            ),
+        );
+    }
+
+    let graph = match PlanGraph::build(plan) {
+        Ok(graph) => Some(graph),
+        Err(error) => {
+            report("broken-reference", Severity::Error, None, error);
+            None
+        }
+    };
+
+    for (index, task) in plan.tasks.iter().enumerate() {
+        let number = Some(task.task_number);
+
+        if registry.find_by_id(&task.command).is_none() {
+            report(
+                "unknown-tool",
+                Severity::Error,
+                number,
+                format!("`{}` is not in the tool registry", task.command),
+            );
+        }
+
+        for arg in &task.args {
+            if let Some(reason) = shell_syntax(arg) {
+                report(
+                    "shell-metacharacters",
+                    Severity::Warn,
+                    number,
+                    format!(
+                        "argument `{arg}` looks like {reason}, but tasks are not run by a shell"
+                    ),
+                );
+            }
+        }
+
+        if task.timeout_secs < SHORTEST_TIMEOUT_SECS {
+            report(
+                "suspicious-timeout",
+                Severity::Warn,
+                number,
+                format!(
+                    "timeout of {}s will likely kill the task before it finishes",
+                    task.timeout_secs
+                ),
+            );
+        } else if task.timeout_secs > LONGEST_TIMEOUT_SECS {
+            report(
+                "suspicious-timeout",
+                Severity::Warn,
+                number,
+                format!(
+                    "timeout of {}s lets a stuck task hold a worker for over an hour",
+                    task.timeout_secs
+                ),
+            );
+        }
+
+        for reference in task.input_tasks().unwrap_or_default() {
+            let later = plan.tasks[index + 1..]
+                .iter()
+                .any(|other| other.task_number == reference);
+            if later {
+                report(
+                    "forward-input",
+                    Severity::Warn,
+                    number,
+                    format!("reads the output of task {reference}, which comes after it"),
+                );
+            }
+        }
+
+        let Some(graph) = &graph else {
+            continue;
+        };
+
+        let sorted =
+            matches!(graph.sources(index), [source] if plan.tasks[*source].command == "sort");
+        if task.command == "uniq" && !sorted {
+            report(
+                "uniq-without-sort",
+                Severity::Warn,
+                number,
+                "`uniq` only collapses adjacent lines, and its input is not sorted".to_string(),
+            );
+        }
+    }
+
+    // Every output no task reads is written to STDOUT, so any but the last
+    // ends up mixed in ahead of the final result
+    if let Some(graph) = &graph {
+        if let Some((_, earlier)) = graph.sinks().split_last() {
+            for &index in earlier {
+                report(
+                    "unreachable-output",
+                    Severity::Info,
+                    Some(plan.tasks[index].task_number),
+                    "no task reads this output; it goes to STDOUT before the final output"
+                        .to_string(),
+                );
+            }
+        }
+    }
+
+    findings.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then(a.task_number.cmp(&b.task_number))
+    });
+    findings
+}
+
+pub fn count(findings: &[Finding], severity: Severity) -> usize {
+    findings
+        .iter()
+        .filter(|finding| finding.severity == severity)
+        .count()
+}
+
+/// Counts by severity, e.g. `1 error, 2 warnings, 0 info`
+pub fn summary(findings: &[Finding]) -> String {
+    let errors = count(findings, Severity::Error);
+    let warnings = count(findings, Severity::Warn);
+
+    format!(
+        "{errors} error{}, {warnings} warning{}, {} info",
+        if errors == 1 { "" } else { "s" },
+        if warnings == 1 { "" } else { "s" },
+        count(findings, Severity::Info)
+    )
+}
+
+/// What shell syntax `arg` resembles, if any
+fn shell_syntax(arg: &str) -> Option<&'static str> {
+    if SHELL_OPERATORS.contains(&arg) {
+        return Some("a shell operator");
+    }
+
+    // `>`, `>>out`, `2>&1`, `&>log`, `<in`
+    let redirection = arg.trim_start_matches(|c: char| c.is_ascii_digit() || c == '&');
+    if redirection.starts_with('>') || redirection.starts_with('<') {
+        return Some("a redirection");
+    }
+
+    if arg.contains("$(") || arg.contains('`') {
+        return Some("command substitution");
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{step, PlanStep};
+
+    fn rules(tasks: Vec<PlanStep>) -> Vec<(&'static str, Severity, Option<u32>)> {
+        let plan = WorkflowPlan {
+            tasks,
+            ..Default::default()
+        };
+
+        lint(&plan, &ToolRegistry::new())
+            .into_iter()
+            .map(|finding| (finding.rule, finding.severity, finding.task_number))
+            .collect()
+    }
+
+    #[test]
+    fn clean_plan_has_no_findings() {
+        let findings = rules(vec![
+            step(1, "grep", &["-E", "error|warn"], None),
+            step(2, "sort", &[], None),
+            step(3, "uniq", &["-c"], None),
+        ]);
+
+        assert!(findings.is_empty(), "{findings:?}");
+    }
+
+    #[test]
+    fn ranks_findings_by_severity() {
+        let mut tasks = vec![
+            step(1, "grep", &["error", ">", "out.txt"], None),
+            step(2, "uniq", &[], None),
+            step(3, "frobnicate", &[], None),
+        ];
+        tasks[1].timeout_secs = 1;
+
+        assert_eq!(
+            rules(tasks),
+            [
+                ("unknown-tool", Severity::Error, Some(3)),
+                ("shell-metacharacters", Severity::Warn, Some(1)),
+                ("suspicious-timeout", Severity::Warn, Some(2)),
+                ("uniq-without-sort", Severity::Warn, Some(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_wiring_problems() {
+        let findings = rules(vec![
+            step(1, "sort", &[], Some(3)),
+            step(2, "cut", &["-f1"], Some(3)),
+            step(3, "grep", &["x"], None),
+            step(4, "tr", &["a-z", "A-Z"], Some(1)),
+        ]);
+        assert_eq!(
+            findings,
+            [
+                ("forward-input", Severity::Warn, Some(1)),
+                ("forward-input", Severity::Warn, Some(2)),
+                ("unreachable-output", Severity::Info, Some(2)),
+            ]
+        );
+
+        let findings = rules(vec![
+            step(1, "sort", &[], Some(2)),
+            step(2, "uniq", &[], Some(1)),
+        ]);
+        assert_eq!(findings[0].0, "broken-reference");
+
+        let findings = rules((1..=101).map(|n| step(n, "sort", &[], None)).collect());
+        assert_eq!(findings[0], ("too-many-tasks", Severity::Error, None));
+    }
+
+    #[test]
+    fn recognizes_shell_syntax() {
+        for arg in [
+            "|", "&&", ";", ">", ">>out", "2>&1", "<", "<in.txt", "$(date)", "`id`",
+        ] {
+            assert!(shell_syntax(arg).is_some(), "{arg}");
+        }
+        for arg in ["a|b", "-E", "x>y", "$HOME", "{}", "10"] {
+            assert!(shell_syntax(arg).is_none(), "{arg}");
+        }
+    }
+}
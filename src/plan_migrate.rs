@@ -0,0 +1,273 @@
+//! Upgrades of plan documents written against older schema versions.
+//!
+//! The version of a document is its `schema_version` or, for documents
+//! written before that field existed, inferred from its shape: a `plan`
+//! list of `cmd` steps or command strings is 0.1, a `tasks` list is 0.2,
+//! and bare arrays of either are wrapped first. Each migration upgrades a
+//! document by one version, and they run in turn until it is current.
+//! Migrations work on the document itself, so keys they do not know about
+//! and fields left to their defaults come through untouched.
+
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+
+use crate::plan::{WorkflowPlan, SCHEMA_VERSION};
+use crate::plan_format::{self, PlanFormat};
+
+type Document = Map<String, Value>;
+type Migration = fn(Document) -> Result<Document, String>;
+
+/// `(from, to, migration)`, one per version
+const MIGRATIONS: &[(&str, &str, Migration)] = &[("0.1", "0.2", v0_1_to_v0_2)];
+
+/// Rewrite the plan file at `path` at the current schema version, in the
+/// format of its extension. Returns the version it was at, or `None` when it
+/// was already current and left untouched.
+pub fn migrate_file(path: &Path) -> Result<Option<String>, String> {
+    let format = PlanFormat::from_path(path);
+    let contents = std::fs::read_to_string(path)
+        .map_err(|error| format!("failed to read plan file {}: {error}", path.display()))?;
+    let original = plan_format::read_document(&contents, format)
+        .map_err(|error| format!("failed to parse plan file {}: {error}", path.display()))?;
+
+    let (migrated, from) = migrate(original.clone())?;
+    serde_json::from_value::<WorkflowPlan>(migrated.clone())
+        .map_err(|error| format!("invalid plan after migration: {error}"))?;
+
+    if migrated == original {
+        return Ok(None);
+    }
+
+    let text = plan_format::write_document(&migrated, format)?;
+    std::fs::write(path, text)
+        .map_err(|error| format!("failed to write plan file {}: {error}", path.display()))?;
+
+    Ok(Some(from))
+}
+
+/// Bring `document` up to [`SCHEMA_VERSION`]. Returns the migrated document
+/// and the version it was at.
+pub fn migrate(document: Value) -> Result<(Value, String), String> {
+    let mut document = wrap(document)?;
+    let from = detect_version(&document)?;
+
+    let mut version = from.clone();
+    while version != SCHEMA_VERSION {
+        let (_, to, migration) = MIGRATIONS
+            .iter()
+            .find(|(from, _, _)| *from == version)
+            .ok_or_else(|| {
+                format!(
+                    "unsupported schema_version {version} (this agx reads up to {SCHEMA_VERSION})"
+                )
+            })?;
+
+        document = migration(document)
+            .map_err(|error| format!("migrating from schema_version {version}: {error}"))?;
+        version = to.to_string();
+    }
+
+    document.insert("schema_version".to_string(), json!(SCHEMA_VERSION));
+
+    Ok((Value::Object(document), from))
+}
+
+/// Turn a bare array of tasks or steps into a document
+fn wrap(document: Value) -> Result<Document, String> {
+    match document {
+        Value::Object(document) => Ok(document),
+        Value::Array(items) => {
+            let current =
+                items.is_empty() || items.iter().any(|item| item.get("command").is_some());
+            let key = if current { "tasks" } else { "plan" };
+
+            Ok(Document::from_iter([(
+                key.to_string(),
+                Value::Array(items),
+            )]))
+        }
+        other => Err(format!(
+            "a plan must be a JSON object or array, got {other}"
+        )),
+    }
+}
+
+fn detect_version(document: &Document) -> Result<String, String> {
+    match document.get("schema_version") {
+        Some(Value::String(version)) => Ok(version.clone()),
+        Some(other) => Err(format!("schema_version must be a string, got {other}")),
+        None if !document.contains_key("tasks")
+            && (document.contains_key("plan") || document.contains_key("steps")) =>
+        {
+            Ok("0.1".to_string())
+        }
+        None => Ok(SCHEMA_VERSION.to_string()),
+    }
+}
+
+/// 0.1 listed `plan` (or `steps`) as `cmd` steps or plain command strings,
+/// numbered by `step_number` and piped with `input_from_step`
+fn v0_1_to_v0_2(mut document: Document) -> Result<Document, String> {
+    let steps = match document.remove("plan").or_else(|| document.remove("steps")) {
+        Some(Value::Array(steps)) => steps,
+        Some(other) => return Err(format!("`plan` must be a list of steps, got {other}")),
+        None => Vec::new(),
+    };
+
+    let tasks = steps
+        .into_iter()
+        .enumerate()
+        .map(|(index, step)| {
+            let mut task = match step {
+                Value::String(command) => {
+                    Document::from_iter([("command".to_string(), Value::String(command))])
+                }
+                Value::Object(step) => step,
+                other => {
+                    return Err(format!(
+                        "step {} must be an object or a command string, got {other}",
+                        index + 1
+                    ))
+                }
+            };
+
+            for (old, new) in [
+                ("cmd", "command"),
+                ("step_number", "task_number"),
+                ("input_from_step", "input_from_task"),
+            ] {
+                if let Some(value) = task.remove(old) {
+                    task.insert(new.to_string(), value);
+                }
+            }
+
+            // 0.1 allowed an explicit null for the default timeout
+            if task.get("timeout_secs").is_some_and(Value::is_null) {
+                task.remove("timeout_secs");
+            }
+
+            task.entry("task_number")
+                .or_insert_with(|| json!(index + 1));
+
+            Ok(Value::Object(task))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    document.insert("tasks".to_string(), Value::Array(tasks));
+
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_plan(text: &str) -> Result<WorkflowPlan, String> {
+        plan_format::parse_plan(text, PlanFormat::Json)
+    }
+
+    fn commands(plan: &WorkflowPlan) -> Vec<&str> {
+        plan.tasks
+            .iter()
+            .map(|task| task.command.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn upgrades_legacy_shapes() {
+        let legacy = parse_plan(
+            r#"{"plan": [{"cmd": "sort", "args": ["-r"]}, {"cmd": "uniq", "input_from_step": 1, "timeout_secs": null}]}"#,
+        )
+        .unwrap();
+        assert_eq!(commands(&legacy), ["sort", "uniq"]);
+        assert_eq!(legacy.tasks[0].args, ["-r"]);
+        assert_eq!(legacy.tasks[1].task_number, 2);
+        assert_eq!(legacy.tasks[1].input_from_task, Some(1));
+        assert_eq!(legacy.tasks[1].timeout_secs, 300);
+        assert_eq!(legacy.schema_version, SCHEMA_VERSION);
+
+        for text in [
+            r#"{"plan": ["sort", "uniq"]}"#,
+            r#"["sort", "uniq"]"#,
+            r#"[{"cmd": "sort"}, {"cmd": "uniq"}]"#,
+            r#"[{"task_number": 1, "command": "sort"}, {"task_number": 2, "command": "uniq"}]"#,
+        ] {
+            assert_eq!(
+                commands(&parse_plan(text).unwrap()),
+                ["sort", "uniq"],
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_the_original_version() {
+        let (document, from) = migrate(json!({"plan": ["sort"]})).unwrap();
+        assert_eq!(from, "0.1");
+        assert_eq!(document["schema_version"], SCHEMA_VERSION);
+        assert_eq!(document["tasks"][0]["command"], "sort");
+
+        let (_, from) = migrate(json!({"tasks": []})).unwrap();
+        assert_eq!(from, SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn rewrites_files_only_when_needed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plan.json");
+        std::fs::write(&path, r#"{"plan": [{"cmd": "sort"}]}"#).unwrap();
+
+        assert_eq!(migrate_file(&path).unwrap().as_deref(), Some("0.1"));
+        let plan = parse_plan(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(commands(&plan), ["sort"]);
+
+        assert_eq!(migrate_file(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn keeps_what_it_does_not_migrate() {
+        let dir = tempfile::tempdir().unwrap();
+
+        for (name, text) in [
+            (
+                "plan.json",
+                r#"{"owner": "ops", "plan": [{"cmd": "sort", "note": "keep"}]}"#,
+            ),
+            (
+                "plan.toml",
+                "owner = \"ops\"\n\n[[plan]]\ncmd = \"sort\"\nnote = \"keep\"\n",
+            ),
+        ] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, text).unwrap();
+            assert_eq!(migrate_file(&path).unwrap().as_deref(), Some("0.1"));
+
+            let format = PlanFormat::from_path(&path);
+            let document =
+                plan_format::read_document(&std::fs::read_to_string(&path).unwrap(), format)
+                    .unwrap();
+            assert_eq!(
+                document,
+                json!({
+                    "owner": "ops",
+                    "schema_version": SCHEMA_VERSION,
+                    "tasks": [{"task_number": 1, "command": "sort", "note": "keep"}]
+                }),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let error = migrate(json!({"schema_version": "9.0", "tasks": []})).unwrap_err();
+        assert!(
+            error.starts_with("unsupported schema_version 9.0"),
+            "{error}"
+        );
+
+        assert!(migrate(json!({"schema_version": 2, "tasks": []})).is_err());
+        assert!(migrate(json!("sort")).is_err());
+    }
+}
@@ -0,0 +1,438 @@
+//! Parameterized plans. Task args may contain `{{input.<name>}}`
+//! placeholders naming one of the plan's declared parameters; they are
+//! checked when the plan is submitted and filled in from each input of
+//! `ACTION submit`, so one stored plan serves many invocations. Plans run,
+//! checked or exported locally are filled in from `--params`, or from the
+//! defaults alone. A plan may also declare an `input_schema` that every
+//! input is checked against before the action is sent.
+
+use std::collections::{HashMap, HashSet};
+
+use jsonschema::paths::PathChunk;
+use jsonschema::JSONSchema;
+use serde_json::Value;
+
+use crate::plan::{ParameterType, PlanParameter, PlanStep, WorkflowPlan};
+
+const PREFIX: &str = "input.";
+
+#[derive(Debug, PartialEq)]
+enum Piece<'a> {
+    Text(&'a str),
+    Parameter(&'a str),
+}
+
+/// Check the declarations, and that every placeholder in `tasks` (task
+/// number and args) names a declared parameter
+pub fn validate<'a>(
+    parameters: &[PlanParameter],
+    tasks: impl IntoIterator<Item = (u32, &'a [String])>,
+) -> Result<(), String> {
+    let mut names = HashSet::new();
+
+    for parameter in parameters {
+        let valid_name = !parameter.name.is_empty()
+            && parameter
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid_name {
+            return Err(format!(
+                "invalid parameter name `{}`: use letters, digits, underscore or dash",
+                parameter.name
+            ));
+        }
+
+        if !names.insert(parameter.name.as_str()) {
+            return Err(format!("parameter `{}` is declared twice", parameter.name));
+        }
+
+        if let Some(default) = &parameter.default {
+            check_type(parameter, default).map_err(|error| format!("default of {error}"))?;
+        }
+    }
+
+    for (task_number, args) in tasks {
+        for arg in args {
+            for piece in pieces(arg).map_err(|error| format!("task {task_number}: {error}"))? {
+                if let Piece::Parameter(name) = piece {
+                    if !names.contains(name) {
+                        return Err(format!(
+                            "task {task_number} uses undeclared parameter `{name}`"
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// [`validate`] for the parameters and tasks of `plan`
+pub fn validate_plan(plan: &WorkflowPlan) -> Result<(), String> {
+    validate(
+        &plan.parameters,
+        plan.tasks
+            .iter()
+            .map(|task| (task.task_number, task.args.as_slice())),
+    )
+}
+
+/// The tasks of `plan` with placeholders replaced by the values `input`
+/// gives, or the parameter defaults
+pub fn resolve(plan: &WorkflowPlan, input: &Value) -> Result<Vec<PlanStep>, String> {
+    if plan.parameters.is_empty() {
+        return Ok(plan.tasks.clone());
+    }
+
+    let fields = input
+        .as_object()
+        .ok_or_else(|| "input must be a JSON object holding the plan parameters".to_string())?;
+
+    let mut values: HashMap<&str, Vec<String>> = HashMap::new();
+    for parameter in &plan.parameters {
+        let value = match fields.get(&parameter.name).filter(|value| !value.is_null()) {
+            Some(value) => Some(value),
+            None if parameter.required => {
+                return Err(format!("missing required parameter `{}`", parameter.name));
+            }
+            None => parameter.default.as_ref(),
+        };
+
+        if let Some(value) = value {
+            check_type(parameter, value)?;
+            values.insert(parameter.name.as_str(), render(value));
+        }
+    }
+
+    plan.tasks
+        .iter()
+        .map(|task| {
+            let mut args = Vec::with_capacity(task.args.len());
+
+            for arg in &task.args {
+                match pieces(arg)?.as_slice() {
+                    [Piece::Parameter(name)] => {
+                        args.extend(values.get(name).into_iter().flatten().cloned());
+                    }
+                    pieces => args.push(
+                        pieces
+                            .iter()
+                            .map(|piece| match piece {
+                                Piece::Text(text) => text.to_string(),
+                                Piece::Parameter(name) => values
+                                    .get(name)
+                                    .map(|value| value.join(","))
+                                    .unwrap_or_default(),
+                            })
+                            .collect(),
+                    ),
+                }
+            }
+
+            Ok(PlanStep {
+                args,
+                ..task.clone()
+            })
+        })
+        .collect()
+}
+
+/// `plan` with its placeholders filled in from `input`, or from the parameter
+/// defaults when there is none, so it can run without AGQ
+pub fn resolve_plan(plan: &WorkflowPlan, input: Option<&Value>) -> Result<WorkflowPlan, String> {
+    validate_plan(plan)?;
+
+    let defaults = Value::Object(Default::default());
+    let tasks = resolve(plan, input.unwrap_or(&defaults))?;
+
+    Ok(WorkflowPlan {
+        tasks,
+        ..plan.clone()
+    })
+}
+
+pub fn compile_schema(schema: &Value) -> Result<JSONSchema, String> {
+    JSONSchema::compile(schema).map_err(|error| error.to_string())
+}
+
+/// Check every input against `schema`. Each problem is reported on its own
+/// line, starting with the path of the offending value, e.g.
+/// `inputs[2].columns[0]`.
+pub fn check_inputs(schema: &Value, inputs: &[Value]) -> Result<(), String> {
+    let schema =
+        compile_schema(schema).map_err(|error| format!("invalid input_schema: {error}"))?;
+    let mut problems = Vec::new();
+
+    for (index, input) in inputs.iter().enumerate() {
+        if let Err(errors) = schema.validate(input) {
+            for error in errors {
+                let mut path = format!("inputs[{index}]");
+                for chunk in error.instance_path.iter() {
+                    match chunk {
+                        PathChunk::Property(name) => {
+                            path.push('.');
+                            path.push_str(name);
+                        }
+                        PathChunk::Index(index) => path.push_str(&format!("[{index}]")),
+                        PathChunk::Keyword(_) => {}
+                    }
+                }
+                problems.push(format!("{path}: {error}"));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems.join("\n"))
+    }
+}
+
+/// Split `arg` into literal text and placeholders. Braces not holding an
+/// `input.` reference are left alone.
+fn pieces(arg: &str) -> Result<Vec<Piece<'_>>, String> {
+    let mut pieces = Vec::new();
+    let mut rest = arg;
+
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(name) = after.trim_start().strip_prefix(PREFIX) else {
+            pieces.push(Piece::Text(&rest[..start + 2]));
+            rest = after;
+            continue;
+        };
+
+        let end = name
+            .find("}}")
+            .ok_or_else(|| format!("unterminated placeholder in `{arg}`"))?;
+
+        if start > 0 {
+            pieces.push(Piece::Text(&rest[..start]));
+        }
+        pieces.push(Piece::Parameter(name[..end].trim_end()));
+        rest = &name[end + 2..];
+    }
+
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+
+    Ok(pieces)
+}
+
+fn check_type(parameter: &PlanParameter, value: &Value) -> Result<(), String> {
+    let (matches, expected) = match parameter.kind {
+        ParameterType::String => (value.is_string(), "a string"),
+        ParameterType::Integer => (value.is_i64() || value.is_u64(), "an integer"),
+        ParameterType::Number => (value.is_number(), "a number"),
+        ParameterType::Boolean => (value.is_boolean(), "a boolean"),
+        ParameterType::Array => (
+            value.as_array().is_some_and(|items| {
+                items
+                    .iter()
+                    .all(|item| !item.is_array() && !item.is_object() && !item.is_null())
+            }),
+            "an array of strings, numbers or booleans",
+        ),
+    };
+
+    if matches {
+        Ok(())
+    } else {
+        Err(format!(
+            "parameter `{}` must be {expected}, got {value}",
+            parameter.name
+        ))
+    }
+}
+
+fn render(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(text) => vec![text.clone()],
+        Value::Array(items) => items.iter().flat_map(render).collect(),
+        other => vec![other.to_string()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::step;
+    use serde_json::json;
+
+    fn parameter(name: &str, kind: ParameterType, default: Option<Value>) -> PlanParameter {
+        PlanParameter {
+            name: name.to_string(),
+            kind,
+            default,
+            required: false,
+            description: None,
+        }
+    }
+
+    fn parameterized_plan() -> WorkflowPlan {
+        WorkflowPlan {
+            tasks: vec![
+                step(1, "grep", &["-i", "{{input.pattern}}"], None),
+                step(2, "cut", &["-d,", "-f{{ input.columns }}"], None),
+                step(3, "sort", &["{{input.sort_flags}}"], None),
+            ],
+            parameters: vec![
+                PlanParameter {
+                    required: true,
+                    ..parameter("pattern", ParameterType::String, None)
+                },
+                parameter("columns", ParameterType::Array, Some(json!([1, 2]))),
+                parameter("sort_flags", ParameterType::Array, None),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn args(tasks: &[PlanStep]) -> Vec<Vec<String>> {
+        tasks.iter().map(|task| task.args.clone()).collect()
+    }
+
+    #[test]
+    fn substitutes_values_and_defaults() {
+        let plan = parameterized_plan();
+
+        let tasks = resolve(
+            &plan,
+            &json!({"pattern": "error", "sort_flags": ["-r", "-n"]}),
+        )
+        .unwrap();
+        assert_eq!(
+            args(&tasks),
+            [vec!["-i", "error"], vec!["-d,", "-f1,2"], vec!["-r", "-n"]]
+        );
+
+        let tasks = resolve(&plan, &json!({"pattern": "warn", "columns": [3]})).unwrap();
+        assert_eq!(
+            args(&tasks),
+            [vec!["-i", "warn"], vec!["-d,", "-f3"], Vec::<&str>::new()]
+        );
+    }
+
+    #[test]
+    fn rejects_missing_and_mistyped_values() {
+        let plan = parameterized_plan();
+
+        let error = resolve(&plan, &json!({})).unwrap_err();
+        assert_eq!(error, "missing required parameter `pattern`");
+
+        let error = resolve(&plan, &json!({"pattern": 7})).unwrap_err();
+        assert_eq!(error, "parameter `pattern` must be a string, got 7");
+
+        assert!(resolve(&plan, &json!(["error"])).is_err());
+    }
+
+    #[test]
+    fn resolves_whole_plans_for_local_runs() {
+        let plan = parameterized_plan();
+
+        let resolved = resolve_plan(&plan, Some(&json!({"pattern": "error"}))).unwrap();
+        assert_eq!(
+            args(&resolved.tasks),
+            [
+                vec!["-i", "error"],
+                vec!["-d,", "-f1,2"],
+                Vec::<&str>::new()
+            ]
+        );
+        assert_eq!(resolved.parameters, plan.parameters);
+
+        assert_eq!(
+            resolve_plan(&plan, None).unwrap_err(),
+            "missing required parameter `pattern`"
+        );
+
+        let mut undeclared = plan.clone();
+        undeclared.tasks[0].args[1] = "{{input.patern}}".to_string();
+        assert!(resolve_plan(&undeclared, Some(&json!({"pattern": "error"}))).is_err());
+
+        let plain = WorkflowPlan {
+            tasks: vec![step(1, "sort", &["{{.Name}}"], None)],
+            ..Default::default()
+        };
+        assert_eq!(
+            args(&resolve_plan(&plain, None).unwrap().tasks),
+            [vec!["{{.Name}}"]]
+        );
+    }
+
+    #[test]
+    fn validates_declarations_and_placeholders() {
+        let plan = parameterized_plan();
+        assert!(validate_plan(&plan).is_ok());
+
+        let mut undeclared = plan.clone();
+        undeclared.tasks[0].args[1] = "{{input.patern}}".to_string();
+        assert_eq!(
+            validate_plan(&undeclared).unwrap_err(),
+            "task 1 uses undeclared parameter `patern`"
+        );
+
+        let mut unterminated = plan.clone();
+        unterminated.tasks[0].args[1] = "{{input.pattern".to_string();
+        assert!(validate_plan(&unterminated)
+            .unwrap_err()
+            .contains("unterminated placeholder"));
+
+        let bad_default = [parameter("n", ParameterType::Integer, Some(json!("ten")))];
+        assert_eq!(
+            validate(&bad_default, []).unwrap_err(),
+            "default of parameter `n` must be an integer, got \"ten\""
+        );
+    }
+
+    #[test]
+    fn input_schema_errors_point_at_element_and_field() {
+        let schema = json!({
+            "type": "object",
+            "required": ["pattern"],
+            "properties": {
+                "pattern": {"type": "string"},
+                "columns": {"type": "array", "items": {"type": "integer"}}
+            }
+        });
+
+        assert!(check_inputs(&schema, &[json!({"pattern": "a", "columns": [1]})]).is_ok());
+
+        let error = check_inputs(
+            &schema,
+            &[
+                json!({"pattern": "a"}),
+                json!({"pattern": "b", "columns": [1, "2"]}),
+                json!({}),
+            ],
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.lines().collect::<Vec<_>>(),
+            [
+                r#"inputs[1].columns[1]: "2" is not of type "integer""#,
+                r#"inputs[2]: "pattern" is a required property"#,
+            ]
+        );
+
+        assert!(check_inputs(&json!({"type": "nope"}), &[])
+            .unwrap_err()
+            .starts_with("invalid input_schema"));
+    }
+
+    #[test]
+    fn leaves_other_braces_alone() {
+        assert_eq!(
+            pieces("{{.Name}}").unwrap(),
+            [Piece::Text("{{"), Piece::Text(".Name}}")]
+        );
+        assert_eq!(
+            pieces("a{{input.x}}b").unwrap(),
+            [Piece::Text("a"), Piece::Parameter("x"), Piece::Text("b")]
+        );
+    }
+}
@@ -0,0 +1,439 @@
+//! Deterministic rewrites applied to every plan the planner produces, after
+//! Echo in `PLAN add` and after Delta in `PLAN validate`.
+//!
+//! Each [`Rule`] performs one rewrite at a time, and [`rewrite`] applies the
+//! first rule that matches until none does. Rules only touch tasks whose
+//! removal or change cannot be observed elsewhere in the plan: a task that is
+//! dropped or merged reads from exactly one task that feeds nothing else, and
+//! is not part of a `run_if`, `on_failure` or `retry` arrangement.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::plan::{FailurePolicy, PlanStep, WorkflowPlan};
+use crate::plan_graph::PlanGraph;
+
+pub struct Rule {
+    /// Stable identifier, listed by `--explain`
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Perform one rewrite, or return false when the rule matches nowhere
+    pub apply: fn(&mut WorkflowPlan) -> bool,
+}
+
+/// The rules applied to planner output, in order of precedence
+pub const RULES: &[Rule] = &[
+    Rule {
+        name: "uniq-needs-sort",
+        description: "insert `sort` before a `uniq` whose input is not sorted",
+        apply: uniq_needs_sort,
+    },
+    Rule {
+        name: "dedupe-sort",
+        description: "drop a `sort` repeating the `sort` it reads from",
+        apply: dedupe_sort,
+    },
+    Rule {
+        name: "sort-uniq",
+        description: "merge `sort` followed by a bare `uniq` into `sort -u`",
+        apply: sort_uniq,
+    },
+    Rule {
+        name: "drop-cat",
+        description: "drop a `cat` without arguments, which only copies its input",
+        apply: drop_cat,
+    },
+];
+
+/// A rule that fired, and how many times
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Fired {
+    pub rule: &'static str,
+    pub description: &'static str,
+    pub count: usize,
+}
+
+/// Apply `rules` to `plan` until none matches
+pub fn rewrite(plan: &mut WorkflowPlan, rules: &[Rule]) -> Vec<Fired> {
+    let mut fired: Vec<Fired> = Vec::new();
+
+    // The built-in rules each shrink the plan or settle a `uniq`; the cap
+    // only guards against rules that undo each other
+    let limit = 4 * plan.tasks.len() + 16;
+
+    for _ in 0..limit {
+        let Some(rule) = rules.iter().find(|rule| (rule.apply)(plan)) else {
+            break;
+        };
+
+        match fired.iter_mut().find(|fired| fired.rule == rule.name) {
+            Some(fired) => fired.count += 1,
+            None => fired.push(Fired {
+                rule: rule.name,
+                description: rule.description,
+                count: 1,
+            }),
+        }
+    }
+
+    fired
+}
+
+fn uniq_needs_sort(plan: &mut WorkflowPlan) -> bool {
+    let Ok(graph) = PlanGraph::build(plan) else {
+        return false;
+    };
+
+    let found = (0..plan.tasks.len()).find(|&index| {
+        let task = &plan.tasks[index];
+        let sorted =
+            matches!(graph.sources(index), [source] if plan.tasks[*source].command == "sort");
+
+        task.command == "uniq" && only_flags(task) && !sorted
+    });
+    let Some(index) = found else {
+        return false;
+    };
+
+    let explicit = is_explicit(plan);
+    let number = plan
+        .tasks
+        .iter()
+        .map(|task| task.task_number)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let uniq = &mut plan.tasks[index];
+
+    let sort = PlanStep {
+        task_number: number,
+        command: "sort".to_string(),
+        args: Vec::new(),
+        timeout_secs: uniq.timeout_secs,
+        input_from_task: uniq.input_from_task.take(),
+        inputs_from_tasks: std::mem::take(&mut uniq.inputs_from_tasks),
+        run_if: None,
+        on_failure: FailurePolicy::Stop,
+        retry: None,
+    };
+    if explicit {
+        uniq.input_from_task = Some(number);
+    }
+
+    plan.tasks.insert(index, sort);
+    renumber(plan);
+
+    true
+}
+
+fn sort_uniq(plan: &mut WorkflowPlan) -> bool {
+    let Ok(graph) = PlanGraph::build(plan) else {
+        return false;
+    };
+
+    // Only flags that keep whole lines as the sort key: with `-n`, `-f` or
+    // `-k`, `sort -u` would also drop lines that merely compare equal
+    let found = (0..plan.tasks.len()).find_map(|index| {
+        let task = &plan.tasks[index];
+        let [source] = graph.sources(index) else {
+            return None;
+        };
+        let sort = &plan.tasks[*source];
+
+        let merges = task.command == "uniq"
+            && task.args.is_empty()
+            && sort.command == "sort"
+            && sort.args.iter().all(|arg| arg == "-r" || arg == "-u")
+            && !graph.is_condition(*source)
+            && removable(plan, &graph, index);
+
+        merges.then_some((*source, index))
+    });
+    let Some((sort, uniq)) = found else {
+        return false;
+    };
+
+    let timeout_secs = plan.tasks[uniq].timeout_secs;
+    let sort = &mut plan.tasks[sort];
+    if !sort.args.iter().any(|arg| arg == "-u") {
+        sort.args.push("-u".to_string());
+    }
+    sort.timeout_secs = sort.timeout_secs.max(timeout_secs);
+
+    remove(plan, uniq);
+
+    true
+}
+
+fn dedupe_sort(plan: &mut WorkflowPlan) -> bool {
+    let Ok(graph) = PlanGraph::build(plan) else {
+        return false;
+    };
+
+    let found = (0..plan.tasks.len()).find(|&index| {
+        let task = &plan.tasks[index];
+
+        task.command == "sort"
+            && matches!(graph.sources(index), [source]
+                if plan.tasks[*source].command == "sort" && plan.tasks[*source].args == task.args)
+            && removable(plan, &graph, index)
+    });
+
+    match found {
+        Some(index) => {
+            remove(plan, index);
+            true
+        }
+        None => false,
+    }
+}
+
+fn drop_cat(plan: &mut WorkflowPlan) -> bool {
+    let Ok(graph) = PlanGraph::build(plan) else {
+        return false;
+    };
+
+    let found = (0..plan.tasks.len()).find(|&index| {
+        let task = &plan.tasks[index];
+        task.command == "cat" && task.args.is_empty() && removable(plan, &graph, index)
+    });
+
+    match found {
+        Some(index) => {
+            remove(plan, index);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Whether task `index` passes its input through to a single reader, so
+/// dropping it and wiring that reader to its input changes nothing else
+fn removable(plan: &WorkflowPlan, graph: &PlanGraph, index: usize) -> bool {
+    let task = &plan.tasks[index];
+    let plain = task.run_if.is_none()
+        && task.on_failure == FailurePolicy::Stop
+        && task.retry.is_none()
+        && !graph.is_condition(index)
+        && graph.fallback_for(index).is_none();
+
+    if !plain || plan.tasks.len() < 2 {
+        return false;
+    }
+
+    match (graph.sources(index), graph.consumers(index)) {
+        // Its source becomes the sink in its place, as long as that does
+        // not reorder the plan's output
+        ([source], []) => {
+            let (low, high) = (*source.min(&index), *source.max(&index));
+            graph.consumers(*source) == [index]
+                && !graph.sinks().iter().any(|&sink| low < sink && sink < high)
+                && keeps_references(plan, index, &[])
+        }
+        ([source], _) => graph.consumers(*source) == [index],
+        // Its reader reads the plan input instead
+        ([], [consumer]) => {
+            graph.sources(*consumer) == [index] && keeps_references(plan, index, &[*consumer])
+        }
+        _ => false,
+    }
+}
+
+/// A plan that wires tasks explicitly must keep some reference after task
+/// `index` goes, or every task would start reading its predecessor
+fn keeps_references(plan: &WorkflowPlan, index: usize, cleared: &[usize]) -> bool {
+    !is_explicit(plan)
+        || plan.tasks.iter().enumerate().any(|(position, task)| {
+            position != index
+                && !cleared.contains(&position)
+                && !task.input_tasks().unwrap_or_default().is_empty()
+        })
+}
+
+fn is_explicit(plan: &WorkflowPlan) -> bool {
+    plan.tasks
+        .iter()
+        .any(|task| !task.input_tasks().unwrap_or_default().is_empty())
+}
+
+/// Whether the args leave the command reading STDIN
+fn only_flags(task: &PlanStep) -> bool {
+    task.args.iter().all(|arg| arg.starts_with('-'))
+}
+
+/// Drop task `index`, pointing the tasks that read it at its own inputs
+fn remove(plan: &mut WorkflowPlan, index: usize) {
+    let removed = plan.tasks.remove(index);
+    let replacement = removed.input_tasks().unwrap_or_default();
+
+    for task in &mut plan.tasks {
+        if task.input_from_task == Some(removed.task_number) {
+            task.input_from_task = None;
+            match replacement.as_slice() {
+                [single] => task.input_from_task = Some(*single),
+                many => task.inputs_from_tasks = many.to_vec(),
+            }
+        }
+
+        if task.inputs_from_tasks.contains(&removed.task_number) {
+            task.inputs_from_tasks = task
+                .inputs_from_tasks
+                .iter()
+                .flat_map(|&reference| {
+                    if reference == removed.task_number {
+                        replacement.clone()
+                    } else {
+                        vec![reference]
+                    }
+                })
+                .collect();
+        }
+    }
+
+    renumber(plan);
+}
+
+/// Number the tasks from 1 in plan order, following every reference
+fn renumber(plan: &mut WorkflowPlan) {
+    let numbers: HashMap<u32, u32> = plan
+        .tasks
+        .iter()
+        .enumerate()
+        .map(|(index, task)| (task.task_number, index as u32 + 1))
+        .collect();
+    let number = |task: u32| numbers.get(&task).copied().unwrap_or(task);
+
+    for (index, task) in plan.tasks.iter_mut().enumerate() {
+        task.task_number = index as u32 + 1;
+        task.input_from_task = task.input_from_task.map(number);
+        for reference in task.inputs_from_tasks.iter_mut() {
+            *reference = number(*reference);
+        }
+        if let Some(condition) = task.run_if.as_mut() {
+            condition.set_task(number(condition.task()));
+        }
+        if let FailurePolicy::Fallback(fallback) = task.on_failure {
+            task.on_failure = FailurePolicy::Fallback(number(fallback));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plan::{step, RunCondition};
+
+    fn rewritten(tasks: Vec<PlanStep>) -> (WorkflowPlan, Vec<&'static str>) {
+        let mut plan = WorkflowPlan {
+            tasks,
+            ..Default::default()
+        };
+        let fired = rewrite(&mut plan, RULES);
+
+        (plan, fired.iter().map(|fired| fired.rule).collect())
+    }
+
+    fn commands(plan: &WorkflowPlan) -> Vec<String> {
+        plan.tasks
+            .iter()
+            .map(|task| {
+                std::iter::once(task.command.clone())
+                    .chain(task.args.iter().cloned())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn uniq_gets_sorted_input() {
+        let (plan, fired) = rewritten(vec![step(1, "uniq", &[], None)]);
+        assert_eq!(commands(&plan), ["sort -u"]);
+        assert_eq!(fired, ["uniq-needs-sort", "sort-uniq"]);
+
+        let (plan, fired) = rewritten(vec![
+            step(1, "grep", &["error"], None),
+            step(2, "uniq", &["-c"], None),
+            step(3, "sort", &["-rn"], None),
+        ]);
+        assert_eq!(
+            commands(&plan),
+            ["grep error", "sort", "uniq -c", "sort -rn"]
+        );
+        assert_eq!(fired, ["uniq-needs-sort"]);
+
+        let (plan, fired) = rewritten(vec![
+            step(1, "sort", &["-n"], None),
+            step(2, "uniq", &[], None),
+        ]);
+        assert_eq!(commands(&plan), ["sort -n", "uniq"]);
+        assert!(fired.is_empty());
+    }
+
+    #[test]
+    fn cleans_up_sorts_and_cats() {
+        let (plan, fired) = rewritten(vec![
+            step(1, "cat", &[], None),
+            step(2, "sort", &["-r"], None),
+            step(3, "sort", &["-r"], None),
+            step(4, "uniq", &[], None),
+            step(5, "cat", &[], None),
+        ]);
+        assert_eq!(commands(&plan), ["sort -r -u"]);
+        assert_eq!(fired, ["dedupe-sort", "sort-uniq", "drop-cat"]);
+
+        let (plan, fired) = rewritten(vec![step(1, "cat", &[], None)]);
+        assert_eq!(commands(&plan), ["cat"]);
+        assert!(fired.is_empty());
+    }
+
+    #[test]
+    fn follows_explicit_references() {
+        let (plan, _) = rewritten(vec![
+            step(1, "grep", &["error"], None),
+            step(2, "sort", &[], Some(1)),
+            step(3, "uniq", &[], Some(2)),
+            step(4, "wc", &["-l"], Some(1)),
+            step(5, "head", &["-n", "3"], Some(3)),
+        ]);
+        assert_eq!(
+            commands(&plan),
+            ["grep error", "sort -u", "wc -l", "head -n 3"]
+        );
+        let inputs: Vec<Option<u32>> = plan.tasks.iter().map(|task| task.input_from_task).collect();
+        assert_eq!(inputs, [None, Some(1), Some(1), Some(2)]);
+
+        // The sort feeds another task, which needs the duplicates
+        let (plan, fired) = rewritten(vec![
+            step(1, "sort", &[], None),
+            step(2, "uniq", &[], Some(1)),
+            step(3, "wc", &["-l"], Some(1)),
+        ]);
+        assert_eq!(commands(&plan), ["sort", "uniq", "wc -l"]);
+        assert!(fired.is_empty());
+
+        // Without the cat nothing would be wired explicitly any more
+        let (plan, fired) = rewritten(vec![
+            step(1, "grep", &["a"], None),
+            step(2, "cat", &[], Some(1)),
+            step(3, "wc", &["-l"], None),
+        ]);
+        assert_eq!(commands(&plan), ["grep a", "cat", "wc -l"]);
+        assert!(fired.is_empty());
+    }
+
+    #[test]
+    fn leaves_conditional_tasks_alone() {
+        let mut tasks = vec![
+            step(1, "sort", &[], None),
+            step(2, "uniq", &[], None),
+            step(3, "echo", &["duplicates removed"], None),
+        ];
+        tasks[2].run_if = Some(RunCondition::OutputNotEmpty { task: 2 });
+
+        let (plan, fired) = rewritten(tasks);
+        assert_eq!(commands(&plan), ["sort", "uniq", "echo duplicates removed"]);
+        assert!(fired.is_empty());
+    }
+}
@@ -506,6 +506,7 @@ impl ModelBackend for CandleBackend {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::plan::FailurePolicy;
 
     #[test]
     fn test_sanitize_input_removes_control_characters() {
@@ -729,6 +730,10 @@ mod tests {
                 args: vec![],
                 timeout_secs: 300,
                 input_from_task: None,
+                inputs_from_tasks: Vec::new(),
+                run_if: None,
+                on_failure: FailurePolicy::Stop,
+                retry: None,
             }],
             ..Default::default()
         };
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use crate::input::InputSummary;
-use crate::plan::{PlanStep, WorkflowPlan};
+use crate::plan::{PlanStep, WorkflowPlan, SCHEMA_VERSION};
 use crate::registry::ToolRegistry;
 
 use super::backend::ModelBackend;
@@ -193,6 +193,10 @@ impl Planner {
             plan_id: None,
             plan_description: None,
             tasks: generated.tasks,
+            examples: Vec::new(),
+            parameters: Vec::new(),
+            input_schema: None,
+            schema_version: SCHEMA_VERSION.to_string(),
         };
 
         let raw_json =
@@ -269,6 +273,10 @@ impl Planner {
             plan_id: None,
             plan_description: None,
             tasks: generated.tasks,
+            examples: Vec::new(),
+            parameters: Vec::new(),
+            input_schema: None,
+            schema_version: SCHEMA_VERSION.to_string(),
         };
 
         let raw_json =
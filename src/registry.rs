@@ -1,9 +1,72 @@
+use std::path::{Path, PathBuf};
+
 pub struct Tool {
     pub id: &'static str,
     pub command: &'static str,
     pub description: &'static str,
     pub patterns: &'static [&'static str],
     pub ok_exit_codes: &'static [i32],
+    pub sandbox: SandboxProfile,
+}
+
+/// Restrictions applied when a tool runs locally via `PLAN run`.
+///
+/// Tasks start with an environment containing only `env_allowlist`, in a
+/// temporary working directory that is removed after the run. Limits left at
+/// `None` are inherited from agx.
+#[derive(Debug, Clone, Copy)]
+pub struct SandboxProfile {
+    pub env_allowlist: &'static [&'static str],
+    /// CPU time in seconds (RLIMIT_CPU)
+    pub cpu_secs: Option<u64>,
+    /// Address space in bytes (RLIMIT_AS)
+    pub memory_bytes: Option<u64>,
+    /// Open file descriptors (RLIMIT_NOFILE)
+    pub open_files: Option<u64>,
+    /// Bytes the task may write to STDOUT
+    pub output_bytes: Option<u64>,
+}
+
+impl SandboxProfile {
+    /// CPU time and output grow with the input, so they are left unlimited
+    /// (the task's `timeout_secs` still applies) to let large logs stream
+    /// through; memory and file descriptors do not, and stay capped.
+    pub const DEFAULT: SandboxProfile = SandboxProfile {
+        env_allowlist: &["PATH", "LANG", "LC_ALL", "LC_CTYPE", "LC_COLLATE", "TZ"],
+        cpu_secs: None,
+        memory_bytes: Some(2 * 1024 * 1024 * 1024),
+        open_files: Some(256),
+        output_bytes: None,
+    };
+}
+
+impl Tool {
+    /// Locate the tool's binary the way `Command` would: as-is when it
+    /// contains a slash, otherwise by searching `$PATH`.
+    pub fn resolve_binary(&self) -> Option<PathBuf> {
+        resolve_in_path(self.command)
+    }
+}
+
+pub fn resolve_in_path(command: &str) -> Option<PathBuf> {
+    if command.contains('/') {
+        let path = PathBuf::from(command);
+        return is_executable(&path).then_some(path);
+    }
+
+    let search_path = std::env::var_os("PATH")?;
+
+    std::env::split_paths(&search_path)
+        .map(|dir| dir.join(command))
+        .find(|candidate| is_executable(candidate))
+}
+
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
 }
 
 pub struct ToolRegistry;
@@ -67,6 +130,13 @@ mod tests {
         let registry = ToolRegistry::new();
         assert!(registry.find_by_id("does-not-exist").is_none());
     }
+
+    #[test]
+    fn resolves_binaries_on_path() {
+        let sort = resolve_in_path("sort").expect("sort should be on PATH");
+        assert!(sort.is_absolute());
+        assert!(resolve_in_path("agx-no-such-binary").is_none());
+    }
 }
 
 static TOOLS: &[Tool] = &[
@@ -76,6 +146,11 @@ static TOOLS: &[Tool] = &[
         description: "Sort lines of text.",
         patterns: &["sort", "order", "alphabetize", "sort lines"],
         ok_exit_codes: &[0],
+        // sort buffers its input in memory before spilling to temp files
+        sandbox: SandboxProfile {
+            memory_bytes: Some(4 * 1024 * 1024 * 1024),
+            ..SandboxProfile::DEFAULT
+        },
     },
     Tool {
         id: "uniq",
@@ -83,6 +158,7 @@ static TOOLS: &[Tool] = &[
         description: "Remove duplicate lines.",
         patterns: &["dedupe", "unique", "remove duplicates"],
         ok_exit_codes: &[0],
+        sandbox: SandboxProfile::DEFAULT,
     },
     Tool {
         id: "grep",
@@ -90,6 +166,7 @@ static TOOLS: &[Tool] = &[
         description: "Filter lines that match a pattern.",
         patterns: &["search", "filter", "match", "grep"],
         ok_exit_codes: &[0, 1],
+        sandbox: SandboxProfile::DEFAULT,
     },
     Tool {
         id: "cut",
@@ -97,6 +174,7 @@ static TOOLS: &[Tool] = &[
         description: "Extract fields or columns from lines.",
         patterns: &["columns", "fields", "delimiter", "extract columns"],
         ok_exit_codes: &[0],
+        sandbox: SandboxProfile::DEFAULT,
     },
     Tool {
         id: "tr",
@@ -104,6 +182,7 @@ static TOOLS: &[Tool] = &[
         description: "Translate or delete characters in text.",
         patterns: &["translate", "replace characters", "lowercase", "uppercase"],
         ok_exit_codes: &[0],
+        sandbox: SandboxProfile::DEFAULT,
     },
     Tool {
         id: "jq",
@@ -111,5 +190,6 @@ static TOOLS: &[Tool] = &[
         description: "Filter and transform JSON data.",
         patterns: &["json", "jq", "filter json", "transform json"],
         ok_exit_codes: &[0],
+        sandbox: SandboxProfile::DEFAULT,
     },
 ];
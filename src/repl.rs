@@ -12,6 +12,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::plan::WorkflowPlan;
 use crate::plan_buffer::PlanStorage;
+use crate::plan_params;
 use crate::planner::{ModelBackend, PlanContext, ToolInfo};
 use crate::registry;
 
@@ -116,7 +117,10 @@ pub enum ReplCommand {
     Remove(usize),
     Clear,
     Validate,
-    Submit,
+    Run(Option<String>), // optional sample input file
+    Submit {
+        force: bool,
+    },
     Save,
 
     // Plan operations (AGX-073)
@@ -191,7 +195,20 @@ impl ReplCommand {
             }
             "clear" | "reset" | "new" | "c" => Ok(ReplCommand::Clear),
             "validate" | "v" => Ok(ReplCommand::Validate),
-            "submit" | "s" => Ok(ReplCommand::Submit),
+            "run" => {
+                let input = parts
+                    .get(1)
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.to_string());
+
+                Ok(ReplCommand::Run(input))
+            }
+            "submit" | "s" => match parts.get(1).map(|s| s.trim()) {
+                None | Some("") => Ok(ReplCommand::Submit { force: false }),
+                Some("--force") => Ok(ReplCommand::Submit { force: true }),
+                Some(other) => Err(format!("unexpected argument after submit: {}", other)),
+            },
             "save" => Ok(ReplCommand::Save),
 
             // Plan operations (AGX-073)
@@ -378,8 +395,12 @@ impl Repl {
                 self.cmd_validate()?;
                 Ok(false)
             }
-            ReplCommand::Submit => {
-                self.cmd_submit()?;
+            ReplCommand::Run(input) => {
+                self.cmd_run(input.as_deref())?;
+                Ok(false)
+            }
+            ReplCommand::Submit { force } => {
+                self.cmd_submit(force)?;
                 Ok(false)
             }
             ReplCommand::Save => {
@@ -454,18 +475,16 @@ impl Repl {
             return Err("no tasks generated".to_string());
         }
 
-        // Append to existing plan with overflow check
-        let start_num = u32::try_from(self.state.plan.tasks.len() + 1)
-            .map_err(|_| "plan exceeds maximum task count (2^32)".to_string())?;
-
-        for (i, mut task) in generated.tasks.into_iter().enumerate() {
-            let task_num = start_num.checked_add(i as u32)
-                .ok_or_else(|| "task number overflow".to_string())?;
-            task.task_number = task_num;
-            self.state.plan.tasks.push(task);
+        // Rewrite and append to existing plan the way `PLAN add` does
+        let (generated, rewrites) = WorkflowPlan {
+            tasks: generated.tasks,
+            ..Default::default()
         }
+        .normalize_for_execution();
+        crate::log_rewrites(&rewrites);
+        let added_count = generated.tasks.len();
+        crate::append_plan(&mut self.state.plan, generated)?;
 
-        let added_count = self.state.plan.tasks.len() - start_num as usize + 1;
         println!("✓ Added {} task(s)", added_count);
 
         // Auto-save to plan buffer
@@ -493,6 +512,14 @@ impl Repl {
             if let Some(input_from) = task.input_from_task {
                 println!("     ← input from task {}", input_from);
             }
+            if !task.inputs_from_tasks.is_empty() {
+                let inputs: Vec<String> = task
+                    .inputs_from_tasks
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect();
+                println!("     ← input from tasks {}", inputs.join(", "));
+            }
         }
 
         println!();
@@ -596,8 +623,39 @@ impl Repl {
         Ok(())
     }
 
-    /// Submit plan to AGQ
-    fn cmd_submit(&self) -> Result<(), String> {
+    /// Execute plan locally against a sample input file
+    fn cmd_run(&self, input: Option<&str>) -> Result<(), String> {
+        use crate::executor::{ExecutionInput, Executor};
+
+        if self.state.plan.tasks.is_empty() {
+            return Err("plan is empty, nothing to run".to_string());
+        }
+
+        // STDIN belongs to the editor, so input comes from a file or is empty
+        let input = match input {
+            Some(path) => ExecutionInput::File(PathBuf::from(path)),
+            None => ExecutionInput::Empty,
+        };
+
+        println!(
+            "▶️  Running {} task(s) locally...",
+            self.state.plan.tasks.len()
+        );
+
+        // There is no way to pass parameters here, so they take their defaults
+        let plan = plan_params::resolve_plan(&self.state.plan, None)?;
+
+        let reg = registry::ToolRegistry::new();
+        Executor::new().execute(&plan, &input, &reg)?;
+
+        println!();
+        println!("✓ Run complete");
+
+        Ok(())
+    }
+
+    /// Submit plan to AGQ, refusing lint errors unless `force` is set
+    fn cmd_submit(&self, force: bool) -> Result<(), String> {
         use crate::agq_client::{AgqClient, AgqConfig};
         use crate::build_job_envelope;
 
@@ -605,6 +663,8 @@ impl Repl {
             return Err("plan is empty, nothing to submit".to_string());
         }
 
+        crate::check_lint_errors(&self.state.plan, force)?;
+
         println!("📤 Submitting plan to AGQ...");
 
         // Build job envelope from current plan
@@ -622,6 +682,14 @@ impl Repl {
         let agq_addr = config.addr.clone(); // Store for error messages
         let client = AgqClient::new(config);
 
+        if crate::existing_plan(&client, &plan_id).is_some() {
+            println!("ℹ️  An identical plan is already stored in AGQ; not submitting it again");
+            println!("   Plan ID: {}", plan_id);
+            println!();
+            println!("Use with: agx ACTION submit --plan-id {}", plan_id);
+            return Ok(());
+        }
+
         match client.submit_plan(&job_json) {
             Ok(submission) => {
                 // Save submission metadata (AGX-075)
@@ -880,7 +948,8 @@ impl Repl {
         println!();
         println!("Plan Actions:");
         println!("  [v]alidate             Run Delta model validation");
-        println!("  [s]ubmit               Submit plan to AGQ");
+        println!("  run [file]             Execute plan locally on a sample file");
+        println!("  [s]ubmit [--force]     Submit plan to AGQ (--force despite lint errors)");
         println!("  save                   Manually save session");
         println!();
         println!("Plan Operations:");
@@ -931,6 +1000,7 @@ impl Repl {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::plan::SCHEMA_VERSION;
 
     #[test]
     fn parse_add_command() {
@@ -990,9 +1060,26 @@ mod tests {
         assert_eq!(ReplCommand::parse("validate").unwrap(), ReplCommand::Validate);
     }
 
+    #[test]
+    fn parse_run_command() {
+        assert_eq!(ReplCommand::parse("run").unwrap(), ReplCommand::Run(None));
+        assert_eq!(
+            ReplCommand::parse("run sample.log").unwrap(),
+            ReplCommand::Run(Some("sample.log".to_string()))
+        );
+    }
+
     #[test]
     fn parse_submit_command() {
-        assert_eq!(ReplCommand::parse("submit").unwrap(), ReplCommand::Submit);
+        assert_eq!(
+            ReplCommand::parse("submit").unwrap(),
+            ReplCommand::Submit { force: false }
+        );
+        assert_eq!(
+            ReplCommand::parse("submit --force").unwrap(),
+            ReplCommand::Submit { force: true }
+        );
+        assert!(ReplCommand::parse("submit now").is_err());
     }
 
     #[test]
@@ -1057,7 +1144,10 @@ mod tests {
 
     #[test]
     fn parse_submit_shortcut() {
-        assert_eq!(ReplCommand::parse("s").unwrap(), ReplCommand::Submit);
+        assert_eq!(
+            ReplCommand::parse("s").unwrap(),
+            ReplCommand::Submit { force: false }
+        );
     }
 
     #[test]
@@ -1116,6 +1206,10 @@ mod tests {
                 plan_id: Some("test-plan".to_string()),
                 plan_description: Some("Test plan".to_string()),
                 tasks: vec![],
+                examples: Vec::new(),
+                parameters: Vec::new(),
+                input_schema: None,
+                schema_version: SCHEMA_VERSION.to_string(),
             },
             history: vec!["add test".to_string(), "preview".to_string()],
             last_saved: None,
@@ -1229,7 +1323,7 @@ mod tests {
 
     // Submit command tests (AGX-075)
     #[test]
-    fn submit_rejects_empty_plan() {
+    fn submit_rejects_empty_and_lint_failing_plans() {
         // Create a REPL with empty plan
         use crate::planner::backend::ModelBackend;
         use crate::planner::types::{GeneratedPlan, ModelError, PlanContext};
@@ -1270,8 +1364,16 @@ mod tests {
         assert!(repl.state.plan.tasks.is_empty());
 
         // Try to submit - should fail with empty plan error
-        let result = repl.cmd_submit();
+        let result = repl.cmd_submit(false);
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("plan is empty"));
+
+        // An unknown tool is a lint error, refused before contacting AGQ
+        repl.state
+            .plan
+            .tasks
+            .push(crate::plan::step(1, "no-such-tool", &[], None));
+        let error = repl.cmd_submit(false).unwrap_err();
+        assert!(error.contains("lint error"), "{error}");
     }
 }
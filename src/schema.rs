@@ -0,0 +1,96 @@
+//! JSON Schemas of the plan and job documents, generated from the Rust
+//! types so they cannot drift from what `agx` actually reads. Printed by
+//! `agx SCHEMA plan|job` for anyone writing plans by hand or in another
+//! language.
+
+use schemars::schema_for;
+use serde_json::Value;
+
+use crate::job::JobEnvelope;
+use crate::plan::WorkflowPlan;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaKind {
+    /// `WorkflowPlan`, as kept in the plan buffer and in plan files
+    Plan,
+    /// `JobEnvelope`, as sent to AGQ by `PLAN submit`
+    Job,
+}
+
+impl SchemaKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "plan" => Some(SchemaKind::Plan),
+            "job" => Some(SchemaKind::Job),
+            _ => None,
+        }
+    }
+}
+
+pub fn schema(kind: SchemaKind) -> Value {
+    let schema = match kind {
+        SchemaKind::Plan => schema_for!(WorkflowPlan),
+        SchemaKind::Job => schema_for!(JobEnvelope),
+    };
+
+    serde_json::to_value(schema).expect("schemas always serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn accepts(kind: SchemaKind, document: &Value) -> bool {
+        jsonschema::JSONSchema::compile(&schema(kind))
+            .unwrap()
+            .is_valid(document)
+    }
+
+    #[test]
+    fn plan_schema_matches_the_plan_type() {
+        let plan = json!({
+            "schema_version": "0.2",
+            "tasks": [
+                {"task_number": 1, "command": "grep", "args": ["{{input.pattern}}"]},
+                {
+                    "task_number": 2,
+                    "command": "sort",
+                    "input_from_task": 1,
+                    "run_if": {"type": "output_not_empty", "task": 1},
+                    "on_failure": {"fallback": 3},
+                    "retry": {"max_attempts": 2}
+                },
+                {"task_number": 3, "command": "cat", "on_failure": "continue"}
+            ],
+            "parameters": [{"name": "pattern", "type": "string", "required": true}]
+        });
+
+        serde_json::from_value::<WorkflowPlan>(plan.clone()).unwrap();
+        assert!(accepts(SchemaKind::Plan, &plan));
+
+        assert!(!accepts(
+            SchemaKind::Plan,
+            &json!({"tasks": [{"task_number": 1}]})
+        ));
+        assert!(!accepts(
+            SchemaKind::Plan,
+            &json!({"tasks": [{"task_number": 1, "command": "sort", "on_failure": "retry"}]})
+        ));
+    }
+
+    #[test]
+    fn job_schema_requires_ids() {
+        let job = json!({
+            "job_id": "job-1",
+            "plan_id": "plan-1",
+            "tasks": [{"task_number": 1, "command": "sort"}]
+        });
+
+        assert!(accepts(SchemaKind::Job, &job));
+        assert!(!accepts(
+            SchemaKind::Job,
+            &json!({"tasks": [{"task_number": 1, "command": "sort"}]})
+        ));
+    }
+}